@@ -8,14 +8,48 @@ import { useRouter } from 'next/navigation';
 import { PublicKey, Transaction, SystemProgram } from '@solana/web3.js';
 import { web3, BN } from '@coral-xyz/anchor';
 
+const GEOHASH_ALPHABET = '0123456789bcdefghjkmnpqrstuvwxyz';
+const CAPABILITY_RELAY = 1 << 1;
+
+// Five characters (~5km) is enough for routing without pinpointing the server.
+const encodeGeohash = (latitude: number, longitude: number, precision = 5) => {
+  const lat = [-90, 90];
+  const lon = [-180, 180];
+  let hash = '';
+  let bits = 0;
+  let ch = 0;
+  let even = true;
+  while (hash.length < precision) {
+    const range = even ? lon : lat;
+    const value = even ? longitude : latitude;
+    const mid = (range[0] + range[1]) / 2;
+    ch <<= 1;
+    if (value >= mid) {
+      ch |= 1;
+      range[0] = mid;
+    } else {
+      range[1] = mid;
+    }
+    even = !even;
+    if (++bits === 5) {
+      hash += GEOHASH_ALPHABET[ch];
+      bits = 0;
+      ch = 0;
+    }
+  }
+  return hash;
+};
+
 export default function RegisterNode() {
   const router = useRouter();
   const { publicKey, signTransaction, sendTransaction } = useWallet();
   const { connection } = useConnection();
 
   const [location, setLocation] = useState('');
-  const [city, setCity] = useState('');
+  const [geohash, setGeohash] = useState('');
   const [ipAddress, setIpAddress] = useState('');
+  const [nodeIdentity, setNodeIdentity] = useState('');
+  const [noiseKey, setNoiseKey] = useState('');
   const [bandwidth, setBandwidth] = useState('1000');
   const [stakeAmount, setStakeAmount] = useState('100');
   const [serverType, setServerType] = useState<'dedicated' | 'vps' | 'cloud'>('dedicated');
@@ -36,7 +70,9 @@ export default function RegisterNode() {
       const geoResponse = await fetch(`https://ipapi.co/${data.ip}/json/`);
       const geoData = await geoResponse.json();
       if (geoData.country_code) setLocation(geoData.country_code);
-      if (geoData.city) setCity(geoData.city);
+      if (geoData.latitude != null && geoData.longitude != null) {
+        setGeohash(encodeGeohash(geoData.latitude, geoData.longitude));
+      }
     } catch (error) {
       console.error('Error detecting IP:', error);
       alert('Failed to detect IP address. Please enter manually.');
@@ -46,7 +82,7 @@ export default function RegisterNode() {
   };
 
   const validateForm = () => {
-    if (!location || !city || !ipAddress || !bandwidth || !stakeAmount) {
+    if (!location || !geohash || !ipAddress || !nodeIdentity || !noiseKey || !bandwidth || !stakeAmount) {
       alert('Please fill in all fields');
       return false;
     }
@@ -70,6 +106,24 @@ export default function RegisterNode() {
       return false;
     }
 
+    const geohashRegex = /^[0-9b-hjkmnp-z]{1,5}$/;
+    if (!geohashRegex.test(geohash)) {
+      alert('Please enter a valid geohash (up to 5 characters)');
+      return false;
+    }
+
+    try {
+      new PublicKey(nodeIdentity);
+    } catch {
+      alert('Please enter the node identity public key printed by `veilpool init`');
+      return false;
+    }
+
+    if (!/^[0-9a-fA-F]{64}$/.test(noiseKey)) {
+      alert('Noise public key must be 32 bytes of hex');
+      return false;
+    }
+
     return true;
   };
 
@@ -94,9 +148,10 @@ export default function RegisterNode() {
         return;
       }
 
-      // Derive PDA for node account
+      // Node accounts are keyed by the node's identity key, not the operator wallet
+      const identity = new PublicKey(nodeIdentity);
       const [nodeAccount] = PublicKey.findProgramAddressSync(
-        [Buffer.from('node'), publicKey.toBuffer()],
+        [Buffer.from('node'), identity.toBuffer()],
         NODE_REGISTRY_PROGRAM_ID
       );
 
@@ -105,37 +160,58 @@ export default function RegisterNode() {
         NODE_REGISTRY_PROGRAM_ID
       );
 
+      const metadata = {
+        countryCode: Array.from(Buffer.from(location)),
+        geohash,
+        endpoints: [
+          {
+            addressKind: { ipv4: {} },
+            address: [...ipAddress.split('.').map(Number), ...new Array(12).fill(0)],
+            port: 443,
+            protocol: { tcp: {} },
+          },
+        ],
+        noisePublicKey: Array.from(Buffer.from(noiseKey, 'hex')),
+        capabilities: CAPABILITY_RELAY,
+      };
+      // The registry stores whole Gbps; the form collects Mbps.
+      const bandwidthGbps = Math.max(1, Math.round(parseInt(bandwidth) / 1000));
+
       console.log('Registering node:', {
         operator: publicKey.toBase58(),
+        nodeIdentity: identity.toBase58(),
         nodeAccount: nodeAccount.toBase58(),
-        location: `${location}-${city}`,
-        ipAddress,
-        bandwidth: parseInt(bandwidth),
+        metadata,
+        bandwidthGbps,
         stake: requiredAmount,
       });
 
-      // In production, create actual transaction using Anchor
+      // In production, create actual transaction using Anchor. The node identity
+      // key must co-sign registration, so the transaction is partially signed by
+      // the node software and completed here by the operator wallet.
       /*
       const tx = await program.methods
-        .registerNode(
-          `${location}-${city}`,
-          ipAddress,
-          parseInt(bandwidth)
-        )
-        .accounts({
+        .registerNode(metadata, bandwidthGbps)
+        .accountsPartial({
           nodeAccount,
+          nodeIdentity: identity,
+          directoryPage,
+          globalRegistry,
           operator: publicKey,
-          systemProgram: SystemProgram.programId,
+          stakeTokenVault: null,
+          stakeMint: null,
+          tokenProgram: null,
         })
-        .rpc();
+        .transaction();
 
       await program.methods
         .stakeSol(new BN(requiredAmount))
-        .accounts({
+        .accountsPartial({
           nodeAccount,
           stakeVault,
+          directoryPage,
+          globalRegistry,
           operator: publicKey,
-          systemProgram: SystemProgram.programId,
         })
         .rpc();
       */
@@ -246,17 +322,43 @@ export default function RegisterNode() {
                     <p className="text-sm text-gray-400 mt-1">ISO 3166-1 alpha-2</p>
                   </div>
                   <div>
-                    <label className="block text-sm font-medium mb-2">City *</label>
+                    <label className="block text-sm font-medium mb-2">Geohash *</label>
                     <input
                       type="text"
-                      value={city}
-                      onChange={(e) => setCity(e.target.value)}
-                      placeholder="San Francisco"
+                      value={geohash}
+                      onChange={(e) => setGeohash(e.target.value.toLowerCase())}
+                      placeholder="9q8yy"
+                      maxLength={5}
                       className="w-full px-4 py-3 bg-white/5 border border-white/10 rounded-lg focus:outline-none focus:border-purple-500"
                     />
+                    <p className="text-sm text-gray-400 mt-1">Up to 5 characters (~5km precision)</p>
                   </div>
                 </div>
 
+                {/* Node Keys */}
+                <div>
+                  <label className="block text-sm font-medium mb-2">Node Identity Public Key *</label>
+                  <input
+                    type="text"
+                    value={nodeIdentity}
+                    onChange={(e) => setNodeIdentity(e.target.value.trim())}
+                    placeholder="Printed by veilpool init"
+                    className="w-full px-4 py-3 bg-white/5 border border-white/10 rounded-lg focus:outline-none focus:border-purple-500 font-mono"
+                  />
+                </div>
+                <div>
+                  <label className="block text-sm font-medium mb-2">Noise Public Key (hex) *</label>
+                  <input
+                    type="text"
+                    value={noiseKey}
+                    onChange={(e) => setNoiseKey(e.target.value.trim())}
+                    placeholder="64 hex characters"
+                    maxLength={64}
+                    className="w-full px-4 py-3 bg-white/5 border border-white/10 rounded-lg focus:outline-none focus:border-purple-500 font-mono"
+                  />
+                  <p className="text-sm text-gray-400 mt-1">Used by clients to open encrypted sessions to your node</p>
+                </div>
+
                 {/* Bandwidth */}
                 <div>
                   <label className="block text-sm font-medium mb-2">
@@ -1,14 +1,57 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
 
 declare_id!("4STuqLYGcLs9Py4TfyBct1dn8pSgMiFsPygifp47bpXo");
 
-const MIN_STAKE: u64 = 100_000_000_000;
-const UNBONDING_PERIOD: i64 = 604800;
-const MIN_REPUTATION: u8 = 50;
-const PROTOCOL_FEE_BPS: u16 = 2000;
-const DOWNTIME_SLASH_BPS: u16 = 500;
-const MALICIOUS_SLASH_BPS: u16 = 5000;
+/// vrf-selection, whose fulfilled requests prove a quality reporter was
+/// actually routed through the node it rates.
+pub const VRF_SELECTION_PROGRAM_ID: Pubkey =
+    anchor_lang::solana_program::pubkey!("4SD36sZLcudbMwUqpd9Efp2iBrN5ihMWj8d59aFAoQFT");
+
+const DEFAULT_MIN_STAKE: u64 = 100_000_000_000;
+const DEFAULT_UNBONDING_PERIOD: i64 = 604800;
+const DEFAULT_MIN_REPUTATION: u8 = 50;
+const DEFAULT_PROTOCOL_FEE_BPS: u16 = 2000;
+const DEFAULT_DOWNTIME_SLASH_BPS: u16 = 500;
+const DEFAULT_MALICIOUS_SLASH_BPS: u16 = 5000;
+const DEFAULT_HEARTBEAT_TIMEOUT: i64 = 3600;
+const DEFAULT_DOWNTIME_BOUNTY_BPS: u16 = 1000;
+const DEFAULT_DOWNTIME_COOLDOWN: i64 = 86400;
+const MIN_DOWNTIME_COOLDOWN: i64 = 3600;
+const DEFAULT_DISPUTE_WINDOW: i64 = 259200;
+const DEFAULT_SLASH_BOND: u64 = 1_000_000_000;
+const DEFAULT_ARBITER_QUORUM: u8 = 1;
+const DEFAULT_ARBITRATION_PERIOD: i64 = 604800;
+const DEFAULT_EPOCH_DURATION: i64 = 86400;
+const DEFAULT_MAX_BANDWIDTH_GBPS: u16 = 100;
+const DEFAULT_REWARD_CLAIM_PERIOD: i64 = 2_592_000;
+const MAX_REWARD_STAKE_MULTIPLIER: u64 = 10;
+const MAX_UNBONDING_PERIOD: i64 = 7_776_000;
+const UPTIME_EPOCH_SECONDS: i64 = 3600;
+const UPTIME_WINDOW_EPOCHS: i64 = 128;
+const REPUTATION_DECAY_PERIOD: i64 = 2_592_000;
+const SLASH_REPUTATION_PENALTY: u64 = 25;
+const FULL_BANDWIDTH_SCORE_GB: u64 = 100_000;
+const FULL_STAKE_AGE_SCORE: i64 = 7_776_000;
+const QUALITY_REPORT_COOLDOWN: i64 = 86400;
+const MAX_REPUTATION_ADJUSTMENT_STEP: i16 = 10;
+const MAX_REPUTATION_ADJUSTMENT: i16 = 20;
+const MAX_EARNINGS_SOURCES: usize = 8;
+const MAX_ARBITERS: usize = 5;
+const MAX_MULTISIG_SIGNERS: usize = 5;
+const MAX_ENDPOINTS: usize = 4;
+const DIRECTORY_PAGE_SIZE: usize = 96;
+const MAX_GEOHASH_LEN: usize = 12;
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+pub const CAPABILITY_EXIT: u8 = 1 << 0;
+pub const CAPABILITY_RELAY: u8 = 1 << 1;
+pub const CAPABILITY_IPV6: u8 = 1 << 2;
+pub const CAPABILITY_UDP: u8 = 1 << 3;
+const KNOWN_CAPABILITIES: u8 = CAPABILITY_EXIT | CAPABILITY_RELAY | CAPABILITY_IPV6 | CAPABILITY_UDP;
+const MAX_UNBONDING_TICKETS: u16 = 8;
+const MAX_COMMISSION_BPS: u16 = 10000;
+const REWARD_PRECISION: u128 = 1_000_000_000_000;
 
 #[program]
 pub mod node_registry {
@@ -22,6 +65,39 @@ pub mod node_registry {
         registry.protocol_fee_vault = ctx.accounts.protocol_fee_vault.key();
         registry.total_bandwidth_served = 0;
         registry.total_earnings_distributed = 0;
+        registry.earnings_sources = Vec::new();
+        registry.stake_mint = None;
+        registry.config = RegistryConfig {
+            min_stake: DEFAULT_MIN_STAKE,
+            unbonding_period: DEFAULT_UNBONDING_PERIOD,
+            min_reputation: DEFAULT_MIN_REPUTATION,
+            protocol_fee_bps: DEFAULT_PROTOCOL_FEE_BPS,
+            downtime_slash_bps: DEFAULT_DOWNTIME_SLASH_BPS,
+            malicious_slash_bps: DEFAULT_MALICIOUS_SLASH_BPS,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            downtime_bounty_bps: DEFAULT_DOWNTIME_BOUNTY_BPS,
+            downtime_cooldown: DEFAULT_DOWNTIME_COOLDOWN,
+            dispute_window: DEFAULT_DISPUTE_WINDOW,
+            slash_bond: DEFAULT_SLASH_BOND,
+            arbiter_quorum: DEFAULT_ARBITER_QUORUM,
+            arbitration_period: DEFAULT_ARBITRATION_PERIOD,
+            epoch_duration: DEFAULT_EPOCH_DURATION,
+            epoch_emission: 0,
+            max_bandwidth_gbps: DEFAULT_MAX_BANDWIDTH_GBPS,
+            reward_claim_period: DEFAULT_REWARD_CLAIM_PERIOD,
+        };
+        registry.arbiters = Vec::new();
+        registry.directory_pages = 0;
+        registry.directory_len = 0;
+        registry.pending_authority = None;
+        registry.multisig_signers = Vec::new();
+        registry.multisig_threshold = 0;
+        registry.is_paused = false;
+        registry.unpaused_at = 0;
+        registry.current_epoch = 0;
+        registry.epoch_started_at = Clock::get()?.unix_timestamp;
+        registry.epoch_total_points = 0;
+        registry.rewards_reserved = 0;
         
         emit!(RegistryInitialized {
             authority: registry.authority,
@@ -31,24 +107,291 @@ pub mod node_registry {
         Ok(())
     }
 
+    pub fn add_earnings_source(ctx: Context<ManageEarningsSources>, source: Pubkey) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        require!(
+            registry.earnings_sources.len() < MAX_EARNINGS_SOURCES,
+            ErrorCode::TooManyEarningsSources
+        );
+        require!(
+            !registry.earnings_sources.contains(&source),
+            ErrorCode::EarningsSourceAlreadyRegistered
+        );
+
+        registry.earnings_sources.push(source);
+
+        emit!(EarningsSourceAdded {
+            source,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_earnings_source(
+        ctx: Context<ManageEarningsSources>,
+        source: Pubkey,
+    ) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        let index = registry
+            .earnings_sources
+            .iter()
+            .position(|registered| *registered == source)
+            .ok_or(ErrorCode::EarningsSourceNotRegistered)?;
+
+        registry.earnings_sources.remove(index);
+
+        emit!(EarningsSourceRemoved {
+            source,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_arbiter(ctx: Context<ManageArbiters>, arbiter: Pubkey) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        require!(registry.arbiters.len() < MAX_ARBITERS, ErrorCode::TooManyArbiters);
+        require!(!registry.arbiters.contains(&arbiter), ErrorCode::ArbiterAlreadyRegistered);
+
+        registry.arbiters.push(arbiter);
+
+        emit!(ArbiterAdded {
+            arbiter,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_arbiter(ctx: Context<ManageArbiters>, arbiter: Pubkey) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        let index = registry
+            .arbiters
+            .iter()
+            .position(|registered| *registered == arbiter)
+            .ok_or(ErrorCode::ArbiterNotRegistered)?;
+
+        registry.arbiters.remove(index);
+
+        emit!(ArbiterRemoved {
+            arbiter,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_config(ctx: Context<UpdateConfig>, config: RegistryConfig) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        require!(config.min_stake > 0, ErrorCode::InvalidConfig);
+        require!(
+            config.unbonding_period > 0 && config.unbonding_period <= MAX_UNBONDING_PERIOD,
+            ErrorCode::InvalidConfig
+        );
+        require!(config.min_reputation <= 100, ErrorCode::InvalidConfig);
+        require!(config.protocol_fee_bps <= 10000, ErrorCode::InvalidConfig);
+        require!(
+            config.downtime_slash_bps <= config.malicious_slash_bps
+                && config.malicious_slash_bps <= 10000,
+            ErrorCode::InvalidConfig
+        );
+        require!(config.heartbeat_timeout > 0, ErrorCode::InvalidConfig);
+        require!(config.downtime_bounty_bps <= 10000, ErrorCode::InvalidConfig);
+        require!(config.downtime_cooldown >= MIN_DOWNTIME_COOLDOWN, ErrorCode::InvalidConfig);
+        require!(
+            config.dispute_window > 0 && config.dispute_window <= MAX_UNBONDING_PERIOD,
+            ErrorCode::InvalidConfig
+        );
+        require!(config.slash_bond > 0, ErrorCode::InvalidConfig);
+        require!(
+            config.arbiter_quorum > 0 && config.arbiter_quorum as usize <= MAX_ARBITERS,
+            ErrorCode::InvalidConfig
+        );
+        require!(
+            config.arbitration_period > 0 && config.arbitration_period <= MAX_UNBONDING_PERIOD,
+            ErrorCode::InvalidConfig
+        );
+        require!(config.epoch_duration > 0, ErrorCode::InvalidConfig);
+        require!(config.max_bandwidth_gbps > 0, ErrorCode::InvalidConfig);
+        require!(config.reward_claim_period > 0, ErrorCode::InvalidConfig);
+
+        let registry = &mut ctx.accounts.global_registry;
+        let old_config = registry.config.clone();
+        registry.config = config.clone();
+
+        emit!(ConfigUpdated {
+            old_config,
+            new_config: config,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Switches staking between native SOL (`None`) and an SPL token, together
+    /// with `min_stake` in the new denomination. Only allowed while no nodes
+    /// are registered, so stake totals and minimums never mix units.
+    pub fn set_stake_mint(
+        ctx: Context<SetStakeMint>,
+        stake_mint: Option<Pubkey>,
+        min_stake: u64,
+    ) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        require!(registry.total_nodes == 0, ErrorCode::NodesRegistered);
+        require!(min_stake > 0, ErrorCode::InvalidConfig);
+
+        registry.stake_mint = stake_mint;
+        registry.config.min_stake = min_stake;
+
+        emit!(StakeMintUpdated {
+            stake_mint,
+            min_stake,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// First step of an authority handover; `None` cancels a pending one.
+    pub fn propose_authority(
+        ctx: Context<ProposeAuthority>,
+        new_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.pending_authority = new_authority;
+
+        emit!(AuthorityTransferProposed {
+            authority: registry.authority,
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let registry = &mut ctx.accounts.global_registry;
+        require!(
+            registry.pending_authority == Some(ctx.accounts.new_authority.key()),
+            ErrorCode::NoPendingAuthorityTransfer
+        );
+
+        let previous_authority = registry.authority;
+        registry.authority = ctx.accounts.new_authority.key();
+        registry.pending_authority = None;
+
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority: registry.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the M-of-N approvers required alongside the authority on
+    /// privileged instructions. A threshold of 0 disables the check.
+    pub fn set_multisig(
+        ctx: Context<ManageRegistry>,
+        signers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+        require!(signers.len() <= MAX_MULTISIG_SIGNERS, ErrorCode::InvalidMultisig);
+        require!(threshold as usize <= signers.len(), ErrorCode::InvalidMultisig);
+        require!(threshold > 0 || signers.is_empty(), ErrorCode::InvalidMultisig);
+        for (index, signer) in signers.iter().enumerate() {
+            require!(!signers[..index].contains(signer), ErrorCode::InvalidMultisig);
+        }
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.multisig_signers = signers.clone();
+        registry.multisig_threshold = threshold;
+
+        emit!(MultisigUpdated {
+            signers,
+            threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pausing needs only the authority so it can act fast during an
+    /// incident; unpausing also needs multisig approval.
+    pub fn set_paused(ctx: Context<ManageRegistry>, paused: bool) -> Result<()> {
+        if !paused {
+            require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+        }
+
+        let clock = Clock::get()?;
+        let registry = &mut ctx.accounts.global_registry;
+        if registry.is_paused && !paused {
+            registry.unpaused_at = clock.unix_timestamp;
+        }
+        registry.is_paused = paused;
+
+        emit!(PauseUpdated {
+            paused,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless: allocates the next directory page. Registration
+    /// fails with `DirectoryPageMissing` until the page it needs exists.
+    /// Only that page can be created, since selection reads every page.
+    pub fn create_directory_page(ctx: Context<CreateDirectoryPage>) -> Result<()> {
+        let registry = &mut ctx.accounts.global_registry;
+        require!(
+            registry.directory_pages == registry.next_directory_page(),
+            ErrorCode::DirectoryPageNotNeeded
+        );
+        let page = &mut ctx.accounts.directory_page.load_init()?;
+        page.page_index = registry.directory_pages;
+        page.count = 0;
+
+        registry.directory_pages = registry.directory_pages.checked_add(1).unwrap();
+
+        Ok(())
+    }
+
     pub fn register_node(
         ctx: Context<RegisterNode>,
-        location: String,
-        ip_address: String,
+        metadata: NodeMetadata,
         bandwidth_gbps: u16,
     ) -> Result<()> {
-        require!(location.len() <= 64, ErrorCode::LocationTooLong);
-        require!(ip_address.len() <= 45, ErrorCode::IpAddressTooLong);
-        require!(bandwidth_gbps > 0, ErrorCode::InvalidBandwidth);
+        metadata.validate()?;
+        require!(
+            bandwidth_gbps > 0
+                && bandwidth_gbps <= ctx.accounts.global_registry.config.max_bandwidth_gbps,
+            ErrorCode::InvalidBandwidth
+        );
 
         let node = &mut ctx.accounts.node_account;
         let clock = Clock::get()?;
 
+        node.node_id = ctx.accounts.node_identity.key();
         node.operator = ctx.accounts.operator.key();
+        node.pending_operator = None;
         node.stake_amount = 0;
-        node.reputation = 100;
-        node.location = location.clone();
-        node.ip_address = ip_address.clone();
+        node.reputation = 0;
+        node.metadata = metadata.clone();
         node.bandwidth_gbps = bandwidth_gbps;
         node.total_bandwidth_served = 0;
         node.uptime_percentage = 100;
@@ -56,16 +399,79 @@ pub mod node_registry {
         node.earnings_accumulated = 0;
         node.is_active = false;
         node.registered_at = clock.unix_timestamp;
-        node.unbonding_until = 0;
+        node.unbonding_amount = 0;
+        node.unbonding_shares = 0;
+        node.next_ticket_id = 0;
+        node.open_tickets = 0;
         node.slash_count = 0;
+        node.commission_bps = 0;
+        node.delegated_stake = 0;
+        node.delegated_unbonding = 0;
+        node.delegated_unbonding_shares = 0;
+        node.delegation_shares = 0;
+        node.acc_reward_per_share = 0;
+        node.stake_mint = ctx.accounts.global_registry.stake_mint;
+        node.last_downtime_slash = 0;
+        node.uptime_epoch = clock.unix_timestamp.checked_div(UPTIME_EPOCH_SECONDS).unwrap();
+        node.uptime_bitmap = 1;
+        node.last_slashed_at = 0;
+        node.quality_score_total = 0;
+        node.quality_report_count = 0;
+        node.reputation_adjustment = 0;
+        node.adjustment_at = 0;
+        node.pending_slash = false;
+        node.delegation_count = 0;
+        node.directory_page = 0;
+        node.directory_slot = 0;
+        node.pending_bandwidth_gbps = 0;
+        node.bandwidth_effective_epoch = 0;
+        node.open_epoch_points = 0;
+        node.reputation = compute_reputation(node, clock.unix_timestamp).score;
+
+        match (node.stake_mint, &ctx.accounts.stake_mint, &ctx.accounts.stake_token_vault) {
+            (None, None, None) => {}
+            (Some(expected), Some(stake_mint), Some(_)) => {
+                require_keys_eq!(stake_mint.key(), expected, ErrorCode::InvalidStakeMint);
+            }
+            _ => return err!(ErrorCode::MissingStakeTokenAccounts),
+        }
+
+        // Fund the earnings vault's rent reserve up front so deposits of any
+        // size can land in it; only amounts above the reserve are claimable.
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.operator.to_account_info(),
+                to: ctx.accounts.earnings_vault.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(transfer_ctx, Rent::get()?.minimum_balance(0))?;
 
         let registry = &mut ctx.accounts.global_registry;
         registry.total_nodes = registry.total_nodes.checked_add(1).unwrap();
 
+        {
+            let page = &mut ctx.accounts.directory_page.load_mut()?;
+            let slot = page.count;
+            require!((slot as usize) < DIRECTORY_PAGE_SIZE, ErrorCode::DirectoryPageMissing);
+            page.count = page.count.checked_add(1).unwrap();
+            node.directory_page = page.page_index;
+            node.directory_slot = slot;
+            page.entries[slot as usize] = DirectoryEntry::from_node(node.key(), node);
+        }
+        registry.directory_len = registry.directory_len.checked_add(1).unwrap();
+
+        let profile = &mut ctx.accounts.operator_profile;
+        if profile.operator == Pubkey::default() {
+            profile.operator = node.operator;
+            profile.created_at = clock.unix_timestamp;
+        }
+        profile.node_count = profile.node_count.checked_add(1).unwrap();
+
         emit!(NodeRegistered {
+            node_id: node.node_id,
             operator: node.operator,
-            location,
-            ip_address,
+            metadata,
             bandwidth_gbps,
             timestamp: clock.unix_timestamp,
         });
@@ -74,10 +480,13 @@ pub mod node_registry {
     }
 
     pub fn stake_sol(ctx: Context<StakeSol>, amount: u64) -> Result<()> {
-        require!(amount >= MIN_STAKE, ErrorCode::InsufficientStake);
+        require!(!ctx.accounts.global_registry.is_paused, ErrorCode::RegistryPaused);
 
         let node = &mut ctx.accounts.node_account;
         let registry = &mut ctx.accounts.global_registry;
+        let min_stake = registry.config.min_stake;
+        require!(amount >= min_stake, ErrorCode::InsufficientStake);
+        require!(node.stake_mint.is_none(), ErrorCode::WrongStakeMode);
 
         let transfer_ctx = CpiContext::new(
             ctx.accounts.system_program.to_account_info(),
@@ -91,7 +500,45 @@ pub mod node_registry {
         node.stake_amount = node.stake_amount.checked_add(amount).unwrap();
         registry.total_stake = registry.total_stake.checked_add(amount).unwrap();
         
-        if node.stake_amount >= MIN_STAKE && !node.is_active {
+        if node.stake_amount >= min_stake && !node.is_active {
+            node.is_active = true;
+        }
+
+        emit!(StakeDeposited {
+            operator: node.operator,
+            amount,
+            total_stake: node.stake_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    pub fn stake_tokens(ctx: Context<StakeTokens>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.global_registry.is_paused, ErrorCode::RegistryPaused);
+
+        let node = &mut ctx.accounts.node_account;
+        let registry = &mut ctx.accounts.global_registry;
+        let min_stake = registry.config.min_stake;
+        require!(amount >= min_stake, ErrorCode::InsufficientStake);
+        require!(node.stake_mint.is_some(), ErrorCode::WrongStakeMode);
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.operator_token_account.to_account_info(),
+                to: ctx.accounts.stake_token_vault.to_account_info(),
+                authority: ctx.accounts.operator.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        node.stake_amount = node.stake_amount.checked_add(amount).unwrap();
+        registry.total_stake = registry.total_stake.checked_add(amount).unwrap();
+        
+        if node.stake_amount >= min_stake && !node.is_active {
             node.is_active = true;
         }
 
@@ -102,27 +549,56 @@ pub mod node_registry {
             timestamp: Clock::get()?.unix_timestamp,
         });
 
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
         Ok(())
     }
 
     pub fn unstake_sol(ctx: Context<UnstakeSol>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
         let node = &mut ctx.accounts.node_account;
+        require!(!node.pending_slash, ErrorCode::SlashProposalPending);
         require!(node.stake_amount >= amount, ErrorCode::InsufficientBalance);
+        require!(node.open_tickets < MAX_UNBONDING_TICKETS, ErrorCode::TooManyUnbondingTickets);
         
         let remaining = node.stake_amount.checked_sub(amount).unwrap();
-        require!(remaining == 0 || remaining >= MIN_STAKE, ErrorCode::InsufficientStake);
+        let config = &ctx.accounts.global_registry.config;
+        require!(remaining == 0 || remaining >= config.min_stake, ErrorCode::InsufficientStake);
+
+        let shares = issue_unbonding_shares(amount, node.unbonding_amount, node.unbonding_shares)?;
 
         let clock = Clock::get()?;
-        node.unbonding_until = clock.unix_timestamp.checked_add(UNBONDING_PERIOD).unwrap();
-        node.is_active = false;
+        let ticket = &mut ctx.accounts.unbonding_ticket;
+        ticket.node = node.key();
+        ticket.ticket_id = node.next_ticket_id;
+        ticket.amount = amount;
+        ticket.shares = shares;
+        ticket.release_at = clock.unix_timestamp.checked_add(config.unbonding_period).unwrap();
+
+        node.stake_amount = remaining;
+        node.unbonding_amount = node.unbonding_amount.checked_add(amount).unwrap();
+        node.unbonding_shares = node.unbonding_shares.checked_add(shares).unwrap();
+        node.next_ticket_id = node.next_ticket_id.checked_add(1).unwrap();
+        node.open_tickets = node.open_tickets.checked_add(1).unwrap();
+        if remaining == 0 {
+            node.is_active = false;
+        }
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.total_stake = registry.total_stake.checked_sub(amount).unwrap();
 
         emit!(UnstakeInitiated {
             operator: node.operator,
+            ticket_id: ticket.ticket_id,
             amount,
-            unbonding_until: node.unbonding_until,
+            remaining_stake: remaining,
+            unbonding_until: ticket.release_at,
             timestamp: clock.unix_timestamp,
         });
 
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
         Ok(())
     }
 
@@ -131,37 +607,61 @@ pub mod node_registry {
         let node_key = ctx.accounts.node_account.key();
         let node_operator = ctx.accounts.node_account.operator;
         
-        let node = &ctx.accounts.node_account;
-        require!(node.unbonding_until > 0, ErrorCode::NoUnbondingInProgress);
-        require!(clock.unix_timestamp >= node.unbonding_until, ErrorCode::UnbondingPeriodActive);
+        let ticket = &ctx.accounts.unbonding_ticket;
+        require!(clock.unix_timestamp >= ticket.release_at, ErrorCode::UnbondingPeriodActive);
+        require!(!ctx.accounts.node_account.pending_slash, ErrorCode::SlashProposalPending);
 
-        let amount = node.stake_amount;
+        let node = &ctx.accounts.node_account;
+        let amount = redeem_unbonding_shares(ticket.shares, node.unbonding_amount, node.unbonding_shares);
+        let shares = ticket.shares;
+        let ticket_id = ticket.ticket_id;
         
-        let registry = &mut ctx.accounts.global_registry;
-        let bump = ctx.bumps.stake_vault;
-        let signer_seeds: &[&[&[u8]]] = &[&[
-            b"stake",
-            node_key.as_ref(),
-            &[bump],
-        ]];
+        match ctx.accounts.node_account.stake_mint {
+            None => {
+                let bump = ctx.bumps.stake_vault;
+                let signer_seeds: &[&[&[u8]]] = &[&[
+                    b"stake",
+                    node_key.as_ref(),
+                    &[bump],
+                ]];
 
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.system_program.to_account_info(),
-            anchor_lang::system_program::Transfer {
-                from: ctx.accounts.stake_vault.to_account_info(),
-                to: ctx.accounts.operator.to_account_info(),
-            },
-            signer_seeds,
-        );
-        anchor_lang::system_program::transfer(transfer_ctx, amount)?;
+                let transfer_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.stake_vault.to_account_info(),
+                        to: ctx.accounts.operator.to_account_info(),
+                    },
+                    signer_seeds,
+                );
+                anchor_lang::system_program::transfer(transfer_ctx, amount)?;
+            }
+            Some(_) => {
+                let (Some(stake_token_vault), Some(operator_token_account), Some(token_program)) = (
+                    &ctx.accounts.stake_token_vault,
+                    &ctx.accounts.operator_token_account,
+                    &ctx.accounts.token_program,
+                ) else {
+                    return err!(ErrorCode::MissingStakeTokenAccounts);
+                };
+                transfer_from_stake_token_vault(
+                    token_program,
+                    stake_token_vault,
+                    operator_token_account.to_account_info(),
+                    &node_key,
+                    ctx.bumps.stake_token_vault.unwrap(),
+                    amount,
+                )?;
+            }
+        }
 
         let node = &mut ctx.accounts.node_account;
-        node.stake_amount = 0;
-        node.unbonding_until = 0;
-        registry.total_stake = registry.total_stake.checked_sub(amount).unwrap();
+        node.unbonding_amount = node.unbonding_amount.checked_sub(amount).unwrap();
+        node.unbonding_shares = node.unbonding_shares.checked_sub(shares).unwrap();
+        node.open_tickets = node.open_tickets.checked_sub(1).unwrap();
 
         emit!(StakeWithdrawn {
             operator: node_operator,
+            ticket_id,
             amount,
             timestamp: clock.unix_timestamp,
         });
@@ -169,107 +669,800 @@ pub mod node_registry {
         Ok(())
     }
 
+    pub fn cancel_unbonding(ctx: Context<CancelUnbonding>) -> Result<()> {
+        require!(!ctx.accounts.global_registry.is_paused, ErrorCode::RegistryPaused);
+
+        let ticket = &ctx.accounts.unbonding_ticket;
+        let shares = ticket.shares;
+        let ticket_id = ticket.ticket_id;
+
+        let node = &mut ctx.accounts.node_account;
+        let amount = redeem_unbonding_shares(shares, node.unbonding_amount, node.unbonding_shares);
+        node.unbonding_amount = node.unbonding_amount.checked_sub(amount).unwrap();
+        node.unbonding_shares = node.unbonding_shares.checked_sub(shares).unwrap();
+        node.open_tickets = node.open_tickets.checked_sub(1).unwrap();
+        node.stake_amount = node.stake_amount.checked_add(amount).unwrap();
+
+        let registry = &mut ctx.accounts.global_registry;
+        if node.stake_amount >= registry.config.min_stake && !node.is_active {
+            node.is_active = true;
+        }
+
+        registry.total_stake = registry.total_stake.checked_add(amount).unwrap();
+
+        emit!(UnbondingCancelled {
+            operator: node.operator,
+            ticket_id,
+            amount,
+            total_stake: node.stake_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
     pub fn update_heartbeat(ctx: Context<UpdateHeartbeat>, bandwidth_served_gb: u64) -> Result<()> {
+        require!(!ctx.accounts.global_registry.is_paused, ErrorCode::RegistryPaused);
+
+        let current_epoch = ctx.accounts.global_registry.current_epoch;
+        let epoch_started_at = ctx.accounts.global_registry.epoch_started_at;
+        let heartbeat_timeout = ctx.accounts.global_registry.config.heartbeat_timeout;
         let node = &mut ctx.accounts.node_account;
         let clock = Clock::get()?;
         
         require!(node.is_active, ErrorCode::NodeNotActive);
 
+        apply_pending_bandwidth(node, current_epoch);
+
+        // `bandwidth_served_gb` is self-reported and only kept as a statistic;
+        // nothing on chain attests it, so reward points ignore it. Points
+        // accrue for time online this epoch, with a gap longer than the
+        // heartbeat timeout only counting up to the timeout.
+        let online_secs = clock.unix_timestamp
+            .checked_sub(node.last_heartbeat.max(epoch_started_at)).unwrap()
+            .clamp(0, heartbeat_timeout) as u64;
+
         node.last_heartbeat = clock.unix_timestamp;
+        record_uptime(node, clock.unix_timestamp, true);
+        refresh_reputation(node, clock.unix_timestamp, ReputationTrigger::Heartbeat, 0);
         node.total_bandwidth_served = node.total_bandwidth_served.checked_add(bandwidth_served_gb).unwrap();
 
         let registry = &mut ctx.accounts.global_registry;
         registry.total_bandwidth_served = registry.total_bandwidth_served.checked_add(bandwidth_served_gb).unwrap();
 
+        let stake_multiplier = node.stake_amount
+            .checked_add(node.delegated_stake).unwrap()
+            .checked_div(registry.config.min_stake).unwrap()
+            .min(MAX_REWARD_STAKE_MULTIPLIER);
+        let points = (online_secs as u128)
+            .checked_mul(node.uptime_percentage as u128).unwrap()
+            .checked_mul(stake_multiplier as u128).unwrap();
+
+        if points > 0 {
+            let created = credit_epoch_points(
+                &ctx.accounts.node_epoch_points,
+                &ctx.accounts.operator,
+                &ctx.accounts.system_program,
+                node.key(),
+                current_epoch,
+                ctx.bumps.node_epoch_points,
+                points,
+            )?;
+            if created {
+                node.open_epoch_points = node.open_epoch_points.checked_add(1).unwrap();
+            }
+            registry.epoch_total_points = registry.epoch_total_points.checked_add(points).unwrap();
+        }
+
         emit!(HeartbeatUpdated {
             operator: node.operator,
+            uptime_percentage: node.uptime_percentage,
             timestamp: clock.unix_timestamp,
             bandwidth_served_gb,
         });
 
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
         Ok(())
     }
 
-    pub fn update_reputation(ctx: Context<UpdateReputation>, new_score: u8) -> Result<()> {
-        require!(new_score <= 100, ErrorCode::InvalidReputation);
+    pub fn update_node_metadata(
+        ctx: Context<UpdateNodeMetadata>,
+        metadata: NodeMetadata,
+        bandwidth_gbps: u16,
+    ) -> Result<()> {
+        metadata.validate()?;
+        let registry = &ctx.accounts.global_registry;
+        require!(
+            bandwidth_gbps > 0 && bandwidth_gbps <= registry.config.max_bandwidth_gbps,
+            ErrorCode::InvalidBandwidth
+        );
 
         let node = &mut ctx.accounts.node_account;
-        let old_score = node.reputation;
-        node.reputation = new_score;
+        node.metadata = metadata.clone();
 
-        emit!(ReputationUpdated {
+        // Advertised capacity only changes at epoch boundaries, so selection
+        // sees one figure per node for the whole epoch.
+        apply_pending_bandwidth(node, registry.current_epoch);
+        if bandwidth_gbps == node.bandwidth_gbps {
+            node.pending_bandwidth_gbps = 0;
+        } else {
+            node.pending_bandwidth_gbps = bandwidth_gbps;
+            node.bandwidth_effective_epoch = registry.current_epoch.checked_add(1).unwrap();
+        }
+
+        emit!(NodeMetadataUpdated {
+            node_id: node.node_id,
             operator: node.operator,
-            old_score,
-            new_score,
+            metadata,
+            bandwidth_gbps,
+            effective_epoch: node.bandwidth_effective_epoch,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
         Ok(())
     }
 
-    pub fn slash_node(ctx: Context<SlashNode>, violation_type: ViolationType) -> Result<()> {
-        let node_key = ctx.accounts.node_account.key();
-        let node_operator = ctx.accounts.node_account.operator;
-        
+    /// Applies a bounded authority correction on top of the computed score.
+    /// The adjustment decays with the other reputation inputs.
+    pub fn adjust_reputation(
+        ctx: Context<AdjustReputation>,
+        delta: i16,
+        reason_code: u16,
+    ) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        require!(
+            delta != 0 && delta.abs() <= MAX_REPUTATION_ADJUSTMENT_STEP,
+            ErrorCode::InvalidReputationAdjustment
+        );
+
+        let clock = Clock::get()?;
         let node = &mut ctx.accounts.node_account;
-        let registry = &mut ctx.accounts.global_registry;
-        
-        require!(node.is_active, ErrorCode::NodeNotActive);
+        node.reputation_adjustment = next_adjustment(node, clock.unix_timestamp, delta);
+        node.adjustment_at = clock.unix_timestamp;
 
-        let slash_bps = match violation_type {
-            ViolationType::Downtime => DOWNTIME_SLASH_BPS,
-            ViolationType::Malicious => MALICIOUS_SLASH_BPS,
-        };
+        refresh_reputation(node, clock.unix_timestamp, ReputationTrigger::Adjustment, reason_code);
 
-        let slash_amount = node.stake_amount
-            .checked_mul(slash_bps as u64).unwrap()
-            .checked_div(10000).unwrap();
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
 
-        let bump = ctx.bumps.stake_vault;
-        let signer_seeds: &[&[&[u8]]] = &[&[
-            b"stake",
-            node_key.as_ref(),
-            &[bump],
-        ]];
+        Ok(())
+    }
 
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.system_program.to_account_info(),
-            anchor_lang::system_program::Transfer {
-                from: ctx.accounts.stake_vault.to_account_info(),
-                to: ctx.accounts.protocol_fee_vault.to_account_info(),
-            },
-            signer_seeds,
+    /// Records (or replaces) the caller's 0-100 quality score for a node.
+    pub fn submit_quality_report(ctx: Context<SubmitQualityReport>, score: u8) -> Result<()> {
+        require!(score <= 100, ErrorCode::InvalidReputation);
+
+        let served = load_vrf_request(&ctx.accounts.vrf_request)?;
+        require!(
+            served.user == ctx.accounts.reporter.key()
+                && served.is_fulfilled
+                && served.circuit.contains(&ctx.accounts.node_account.key()),
+            ErrorCode::UnprovenReporter
         );
-        anchor_lang::system_program::transfer(transfer_ctx, slash_amount)?;
 
-        node.stake_amount = node.stake_amount.checked_sub(slash_amount).unwrap();
-        node.slash_count = node.slash_count.checked_add(1).unwrap();
-        registry.total_stake = registry.total_stake.checked_sub(slash_amount).unwrap();
+        let clock = Clock::get()?;
+        let node = &mut ctx.accounts.node_account;
+        let report = &mut ctx.accounts.quality_report;
+        // Each report needs a fresh session through the node.
+        require_keys_neq!(
+            report.last_request,
+            ctx.accounts.vrf_request.key(),
+            ErrorCode::VrfRequestAlreadyUsed
+        );
+
+        if report.reporter == Pubkey::default() {
+            report.node = node.key();
+            report.reporter = ctx.accounts.reporter.key();
+            node.quality_report_count = node.quality_report_count.checked_add(1).unwrap();
+        } else {
+            require!(
+                clock.unix_timestamp.checked_sub(report.submitted_at).unwrap()
+                    >= QUALITY_REPORT_COOLDOWN,
+                ErrorCode::QualityReportCooldownActive
+            );
+            node.quality_score_total = node.quality_score_total
+                .checked_sub(report.score as u64).unwrap();
+        }
+
+        report.score = score;
+        report.submitted_at = clock.unix_timestamp;
+        report.last_request = ctx.accounts.vrf_request.key();
+        node.quality_score_total = node.quality_score_total.checked_add(score as u64).unwrap();
+
+        refresh_reputation(node, clock.unix_timestamp, ReputationTrigger::QualityReport, 0);
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    /// Permissionless: re-applies decay so idle or deactivated nodes recover.
+    pub fn refresh_node_reputation(ctx: Context<RefreshNodeReputation>) -> Result<()> {
+        let node = &mut ctx.accounts.node_account;
+        refresh_reputation(node, Clock::get()?.unix_timestamp, ReputationTrigger::Refresh, 0);
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    /// Authority slash for downtime. Malicious behaviour goes through
+    /// `propose_slash` so the operator can dispute it before funds move.
+    pub fn slash_node(ctx: Context<SlashNode>, violation_type: ViolationType) -> Result<()> {
+        require_multisig(&ctx.accounts.global_registry, ctx.remaining_accounts)?;
+
+        let node_key = ctx.accounts.node_account.key();
+        let node_operator = ctx.accounts.node_account.operator;
+        
+        let node = &mut ctx.accounts.node_account;
+        let registry = &mut ctx.accounts.global_registry;
+        
+        require!(node.is_active, ErrorCode::NodeNotActive);
+        require!(
+            violation_type == ViolationType::Downtime,
+            ErrorCode::MaliciousSlashRequiresProposal
+        );
+
+        let slashed = apply_slash(node, registry.config.downtime_slash_bps);
+        let total_slash = slashed.total();
+
+        match node.stake_mint {
+            None => {
+                let bump = ctx.bumps.stake_vault;
+                let signer_seeds: &[&[&[u8]]] = &[&[
+                    b"stake",
+                    node_key.as_ref(),
+                    &[bump],
+                ]];
+
+                let transfer_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.stake_vault.to_account_info(),
+                        to: ctx.accounts.protocol_fee_vault.to_account_info(),
+                    },
+                    signer_seeds,
+                );
+                anchor_lang::system_program::transfer(transfer_ctx, total_slash)?;
+            }
+            Some(_) => {
+                let (Some(stake_token_vault), Some(protocol_fee_token_account), Some(token_program)) = (
+                    &ctx.accounts.stake_token_vault,
+                    &ctx.accounts.protocol_fee_token_account,
+                    &ctx.accounts.token_program,
+                ) else {
+                    return err!(ErrorCode::MissingStakeTokenAccounts);
+                };
+                require_keys_eq!(
+                    protocol_fee_token_account.owner,
+                    ctx.accounts.protocol_fee_vault.key(),
+                    ErrorCode::InvalidProtocolFeeTokenAccount
+                );
+                transfer_from_stake_token_vault(
+                    token_program,
+                    stake_token_vault,
+                    protocol_fee_token_account.to_account_info(),
+                    &node_key,
+                    ctx.bumps.stake_token_vault.unwrap(),
+                    total_slash,
+                )?;
+            }
+        }
 
-        if node.stake_amount < MIN_STAKE {
+        node.slash_count = node.slash_count.checked_add(1).unwrap();
+        registry.total_stake = registry.total_stake.checked_sub(slashed.bonded()).unwrap();
+
+        if node.stake_amount < registry.config.min_stake {
             node.is_active = false;
         }
 
+        let now = Clock::get()?.unix_timestamp;
+        node.last_slashed_at = now;
+        refresh_reputation(node, now, ReputationTrigger::Slash, 0);
+
         emit!(NodeSlashed {
             operator: node_operator,
             violation_type,
-            slash_amount,
+            slash_amount: slashed.stake,
+            delegated_slash_amount: slashed.delegated,
+            unbonding_slash_amount: slashed.unbonding,
+            delegated_unbonding_slash_amount: slashed.delegated_unbonding,
             remaining_stake: node.stake_amount,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    /// Permissionless crank: slashes an active node whose last heartbeat is
+    /// older than `heartbeat_timeout`, paying part of the slash to the reporter.
+    pub fn report_downtime(ctx: Context<ReportDowntime>) -> Result<()> {
+        require!(!ctx.accounts.global_registry.is_paused, ErrorCode::RegistryPaused);
+
+        let node_key = ctx.accounts.node_account.key();
+        let node_operator = ctx.accounts.node_account.operator;
+        let clock = Clock::get()?;
+
+        let node = &mut ctx.accounts.node_account;
+        let registry = &mut ctx.accounts.global_registry;
+
+        require!(node.is_active, ErrorCode::NodeNotActive);
+        // Heartbeats are blocked while paused, so nodes get a full timeout
+        // after an unpause before they can be reported.
+        let stale_since = node.last_heartbeat.max(registry.unpaused_at);
+        require!(
+            clock.unix_timestamp.checked_sub(stale_since).unwrap()
+                > registry.config.heartbeat_timeout,
+            ErrorCode::HeartbeatNotStale
+        );
+        // One outage is slashed once: the node must have come back up since.
+        require!(
+            node.last_heartbeat > node.last_downtime_slash,
+            ErrorCode::NodeNotRecovered
+        );
+        require!(
+            node.last_downtime_slash == 0
+                || clock.unix_timestamp.checked_sub(node.last_downtime_slash).unwrap()
+                    >= registry.config.downtime_cooldown,
+            ErrorCode::DowntimeCooldownActive
+        );
+
+        let slashed = apply_slash(node, registry.config.downtime_slash_bps);
+        let total_slash = slashed.total();
+
+        let bounty = total_slash
+            .checked_mul(registry.config.downtime_bounty_bps as u64).unwrap()
+            .checked_div(10000).unwrap();
+        let protocol_share = total_slash.checked_sub(bounty).unwrap();
+
+        match node.stake_mint {
+            None => {
+                let bump = ctx.bumps.stake_vault;
+                transfer_from_stake_vault(
+                    &ctx.accounts.system_program,
+                    &ctx.accounts.stake_vault,
+                    ctx.accounts.reporter.to_account_info(),
+                    &node_key,
+                    bump,
+                    bounty,
+                )?;
+                transfer_from_stake_vault(
+                    &ctx.accounts.system_program,
+                    &ctx.accounts.stake_vault,
+                    ctx.accounts.protocol_fee_vault.to_account_info(),
+                    &node_key,
+                    bump,
+                    protocol_share,
+                )?;
+            }
+            Some(_) => {
+                let (
+                    Some(stake_token_vault),
+                    Some(reporter_token_account),
+                    Some(protocol_fee_token_account),
+                    Some(token_program),
+                ) = (
+                    &ctx.accounts.stake_token_vault,
+                    &ctx.accounts.reporter_token_account,
+                    &ctx.accounts.protocol_fee_token_account,
+                    &ctx.accounts.token_program,
+                ) else {
+                    return err!(ErrorCode::MissingStakeTokenAccounts);
+                };
+                require_keys_eq!(
+                    protocol_fee_token_account.owner,
+                    ctx.accounts.protocol_fee_vault.key(),
+                    ErrorCode::InvalidProtocolFeeTokenAccount
+                );
+                let bump = ctx.bumps.stake_token_vault.unwrap();
+                transfer_from_stake_token_vault(
+                    token_program,
+                    stake_token_vault,
+                    reporter_token_account.to_account_info(),
+                    &node_key,
+                    bump,
+                    bounty,
+                )?;
+                transfer_from_stake_token_vault(
+                    token_program,
+                    stake_token_vault,
+                    protocol_fee_token_account.to_account_info(),
+                    &node_key,
+                    bump,
+                    protocol_share,
+                )?;
+            }
+        }
+
+        node.slash_count = node.slash_count.checked_add(1).unwrap();
+        node.last_downtime_slash = clock.unix_timestamp;
+        node.last_slashed_at = clock.unix_timestamp;
+        record_uptime(node, clock.unix_timestamp, false);
+        refresh_reputation(node, clock.unix_timestamp, ReputationTrigger::Downtime, 0);
+        registry.total_stake = registry.total_stake.checked_sub(slashed.bonded()).unwrap();
+
+        if node.stake_amount < registry.config.min_stake {
+            node.is_active = false;
+        }
+
+        emit!(NodeSlashed {
+            operator: node_operator,
+            violation_type: ViolationType::Downtime,
+            slash_amount: slashed.stake,
+            delegated_slash_amount: slashed.delegated,
+            unbonding_slash_amount: slashed.unbonding,
+            delegated_unbonding_slash_amount: slashed.delegated_unbonding,
+            remaining_stake: node.stake_amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        emit!(DowntimeReported {
+            operator: node_operator,
+            reporter: ctx.accounts.reporter.key(),
+            last_heartbeat: node.last_heartbeat,
+            bounty,
+            timestamp: clock.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    /// Opens a malicious-behaviour slash against a node. Only arbiters can
+    /// propose; the proposer posts `slash_bond`, which is forfeited to the
+    /// operator if the other arbiters reject it.
+    pub fn propose_slash(ctx: Context<ProposeSlash>, evidence_hash: [u8; 32]) -> Result<()> {
+        let registry = &ctx.accounts.global_registry;
+        require!(
+            registry.arbiters.contains(&ctx.accounts.reporter.key()),
+            ErrorCode::UnauthorizedArbiter
+        );
+
+        let clock = Clock::get()?;
+        let bond = registry.config.slash_bond;
+        let dispute_window = registry.config.dispute_window;
+        let arbitration_period = registry.config.arbitration_period;
+
+        // Stake that is still unbonding stays answerable for past behaviour,
+        // so deactivating or unstaking does not put a node out of reach.
+        let node = &mut ctx.accounts.node_account;
+        require!(
+            node.stake_amount > 0
+                || node.unbonding_amount > 0
+                || node.delegated_stake > 0
+                || node.delegated_unbonding > 0,
+            ErrorCode::NoSlashableStake
+        );
+        require!(!node.pending_slash, ErrorCode::SlashProposalPending);
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.reporter.to_account_info(),
+                to: ctx.accounts.slash_proposal.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(transfer_ctx, bond)?;
+
+        let proposal = &mut ctx.accounts.slash_proposal;
+        proposal.node = node.key();
+        proposal.reporter = ctx.accounts.reporter.key();
+        proposal.evidence_hash = evidence_hash;
+        proposal.reporter_bond = bond;
+        proposal.counter_bond = 0;
+        proposal.created_at = clock.unix_timestamp;
+        proposal.dispute_deadline = clock.unix_timestamp.checked_add(dispute_window).unwrap();
+        proposal.arbitration_deadline = proposal
+            .dispute_deadline
+            .checked_add(arbitration_period)
+            .unwrap();
+        proposal.disputed = false;
+        proposal.status = SlashProposalStatus::Pending;
+        proposal.voters = Vec::new();
+        proposal.uphold_votes = 0;
+        proposal.reject_votes = 0;
+        proposal.arbiters = registry.arbiters.clone();
+
+        node.pending_slash = true;
+
+        emit!(SlashProposed {
+            operator: node.operator,
+            reporter: proposal.reporter,
+            evidence_hash,
+            dispute_deadline: proposal.dispute_deadline,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Operator appeal: matches the reporter's bond to send the proposal to a
+    /// vote of the other arbiters. Undisputed proposals are upheld once the
+    /// window closes.
+    pub fn dispute_slash(ctx: Context<DisputeSlash>) -> Result<()> {
+        let clock = Clock::get()?;
+        let proposal = &mut ctx.accounts.slash_proposal;
+        require!(!proposal.disputed, ErrorCode::SlashAlreadyDisputed);
+        require!(
+            clock.unix_timestamp < proposal.dispute_deadline,
+            ErrorCode::DisputeWindowClosed
+        );
+
+        let bond = proposal.reporter_bond;
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.operator.to_account_info(),
+                to: proposal.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(transfer_ctx, bond)?;
+
+        proposal.counter_bond = bond;
+        proposal.disputed = true;
+
+        emit!(SlashDisputed {
+            operator: ctx.accounts.operator.key(),
+            counter_bond: bond,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Records one arbiter's vote on a disputed proposal. Only the arbiters
+    /// seated when it was proposed can vote, so rotating the set mid-dispute
+    /// neither adds voters nor unseats them. The proposal is decided once
+    /// either side reaches `arbiter_quorum`; the proposer cannot vote on its
+    /// own proposal.
+    pub fn resolve_slash(ctx: Context<ResolveSlash>, uphold: bool) -> Result<()> {
+        let registry = &ctx.accounts.global_registry;
+        let arbiter = ctx.accounts.arbiter.key();
+        let clock = Clock::get()?;
+        let proposal = &mut ctx.accounts.slash_proposal;
+        require!(proposal.arbiters.contains(&arbiter), ErrorCode::UnauthorizedArbiter);
+        require!(proposal.disputed, ErrorCode::SlashNotDisputed);
+        require!(
+            proposal.status == SlashProposalStatus::Pending,
+            ErrorCode::SlashAlreadyResolved
+        );
+        require!(
+            clock.unix_timestamp < proposal.arbitration_deadline,
+            ErrorCode::ArbitrationClosed
+        );
+        require_keys_neq!(arbiter, proposal.reporter, ErrorCode::ConflictedArbiter);
+        require!(!proposal.voters.contains(&arbiter), ErrorCode::ArbiterAlreadyVoted);
+
+        proposal.voters.push(arbiter);
+        if uphold {
+            proposal.uphold_votes = proposal.uphold_votes.checked_add(1).unwrap();
+        } else {
+            proposal.reject_votes = proposal.reject_votes.checked_add(1).unwrap();
+        }
+
+        let quorum = registry.config.arbiter_quorum;
+        if proposal.uphold_votes >= quorum {
+            proposal.status = SlashProposalStatus::Upheld;
+        } else if proposal.reject_votes >= quorum {
+            proposal.status = SlashProposalStatus::Rejected;
+        }
+
+        emit!(SlashVoteCast {
+            operator: ctx.accounts.node_account.operator,
+            arbiter,
+            uphold,
+            uphold_votes: proposal.uphold_votes,
+            reject_votes: proposal.reject_votes,
+            timestamp: clock.unix_timestamp,
+        });
+
+        if proposal.status != SlashProposalStatus::Pending {
+            emit!(SlashResolved {
+                operator: ctx.accounts.node_account.operator,
+                arbiter,
+                upheld: proposal.status == SlashProposalStatus::Upheld,
+                timestamp: clock.unix_timestamp,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Permissionless once the dispute window has closed. An upheld slash
+    /// pays both bonds to the reporter; a rejected one pays both to the operator.
+    /// A disputed proposal that misses quorum by `arbitration_deadline` is
+    /// dropped and each side gets its own bond back.
+    pub fn execute_slash(ctx: Context<ExecuteSlash>) -> Result<()> {
+        let clock = Clock::get()?;
+        let node_key = ctx.accounts.node_account.key();
+        let node_operator = ctx.accounts.node_account.operator;
+
+        let proposal = &ctx.accounts.slash_proposal;
+        require!(
+            clock.unix_timestamp >= proposal.dispute_deadline,
+            ErrorCode::DisputeWindowOpen
+        );
+        let expired = proposal.disputed
+            && proposal.status == SlashProposalStatus::Pending
+            && clock.unix_timestamp >= proposal.arbitration_deadline;
+        let upheld = match (proposal.disputed, proposal.status) {
+            (false, _) | (true, SlashProposalStatus::Upheld) => true,
+            (true, SlashProposalStatus::Rejected) => false,
+            (true, SlashProposalStatus::Pending) if expired => false,
+            (true, SlashProposalStatus::Pending) => return err!(ErrorCode::AwaitingArbitration),
+        };
+
+        let node = &mut ctx.accounts.node_account;
+        let registry = &mut ctx.accounts.global_registry;
+        node.pending_slash = false;
+
+        if !upheld {
+            // The reporter's account receives the proposal rent on close, plus
+            // its own bond when arbitration timed out.
+            let bonds = if expired {
+                proposal.counter_bond
+            } else {
+                proposal.reporter_bond.checked_add(proposal.counter_bond).unwrap()
+            };
+            let proposal_info = ctx.accounts.slash_proposal.to_account_info();
+            let operator_info = ctx.accounts.operator.to_account_info();
+            **proposal_info.try_borrow_mut_lamports()? =
+                proposal_info.lamports().checked_sub(bonds).unwrap();
+            **operator_info.try_borrow_mut_lamports()? =
+                operator_info.lamports().checked_add(bonds).unwrap();
+
+            emit!(SlashExecuted {
+                operator: node_operator,
+                upheld,
+                slash_amount: 0,
+                timestamp: clock.unix_timestamp,
+            });
+
+            return Ok(());
+        }
+
+        let slashed = apply_slash(node, registry.config.malicious_slash_bps);
+        let total_slash = slashed.total();
+
+        match node.stake_mint {
+            None => {
+                transfer_from_stake_vault(
+                    &ctx.accounts.system_program,
+                    &ctx.accounts.stake_vault,
+                    ctx.accounts.protocol_fee_vault.to_account_info(),
+                    &node_key,
+                    ctx.bumps.stake_vault,
+                    total_slash,
+                )?;
+            }
+            Some(_) => {
+                let (Some(stake_token_vault), Some(protocol_fee_token_account), Some(token_program)) = (
+                    &ctx.accounts.stake_token_vault,
+                    &ctx.accounts.protocol_fee_token_account,
+                    &ctx.accounts.token_program,
+                ) else {
+                    return err!(ErrorCode::MissingStakeTokenAccounts);
+                };
+                require_keys_eq!(
+                    protocol_fee_token_account.owner,
+                    ctx.accounts.protocol_fee_vault.key(),
+                    ErrorCode::InvalidProtocolFeeTokenAccount
+                );
+                transfer_from_stake_token_vault(
+                    token_program,
+                    stake_token_vault,
+                    protocol_fee_token_account.to_account_info(),
+                    &node_key,
+                    ctx.bumps.stake_token_vault.unwrap(),
+                    total_slash,
+                )?;
+            }
+        }
+
+        node.slash_count = node.slash_count.checked_add(1).unwrap();
+        node.last_slashed_at = clock.unix_timestamp;
+        registry.total_stake = registry.total_stake.checked_sub(slashed.bonded()).unwrap();
+
+        if node.stake_amount < registry.config.min_stake {
+            node.is_active = false;
+        }
+
+        refresh_reputation(node, clock.unix_timestamp, ReputationTrigger::Slash, 0);
+
+        emit!(NodeSlashed {
+            operator: node_operator,
+            violation_type: ViolationType::Malicious,
+            slash_amount: slashed.stake,
+            delegated_slash_amount: slashed.delegated,
+            unbonding_slash_amount: slashed.unbonding,
+            delegated_unbonding_slash_amount: slashed.delegated_unbonding,
+            remaining_stake: node.stake_amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        emit!(SlashExecuted {
+            operator: node_operator,
+            upheld,
+            slash_amount: total_slash,
+            timestamp: clock.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
         Ok(())
     }
 
+    /// Pays `amount` lamports from a registered earnings source (pass
+    /// redemption or pool payout settlement) into the node's earnings vault,
+    /// so accrued earnings are always backed by funds the program holds.
     pub fn record_earnings(ctx: Context<RecordEarnings>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.global_registry.is_paused, ErrorCode::RegistryPaused);
+
+        let registry = &ctx.accounts.global_registry;
+        require!(
+            registry.earnings_sources.contains(&ctx.accounts.source.key()),
+            ErrorCode::UnauthorizedEarningsSource
+        );
+
         let node = &mut ctx.accounts.node_account;
         require!(node.is_active, ErrorCode::NodeNotActive);
 
         let protocol_fee = amount
-            .checked_mul(PROTOCOL_FEE_BPS as u64).unwrap()
+            .checked_mul(registry.config.protocol_fee_bps as u64).unwrap()
             .checked_div(10000).unwrap();
         
-        let operator_earnings = amount.checked_sub(protocol_fee).unwrap();
+        let node_earnings = amount.checked_sub(protocol_fee).unwrap();
+
+        // Split the node's share pro-rata between operator and delegated
+        // stake; the operator keeps its commission on the delegated part.
+        let bonded = node.stake_amount.checked_add(node.delegated_stake).unwrap();
+        let delegated_share = if bonded == 0 || node.delegation_shares == 0 {
+            0
+        } else {
+            (node_earnings as u128)
+                .checked_mul(node.delegated_stake as u128).unwrap()
+                .checked_div(bonded as u128).unwrap() as u64
+        };
+        let commission = delegated_share
+            .checked_mul(node.commission_bps as u64).unwrap()
+            .checked_div(10000).unwrap();
+        let delegator_rewards = delegated_share.checked_sub(commission).unwrap();
+        let operator_earnings = node_earnings.checked_sub(delegator_rewards).unwrap();
+
+        if delegator_rewards > 0 {
+            let increment = (delegator_rewards as u128)
+                .checked_mul(REWARD_PRECISION).unwrap()
+                .checked_div(node.delegation_shares as u128).unwrap();
+            node.acc_reward_per_share = node.acc_reward_per_share.checked_add(increment).unwrap();
+        }
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.source.to_account_info(),
+                to: ctx.accounts.earnings_vault.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(transfer_ctx, node_earnings)?;
+
+        if protocol_fee > 0 {
+            let transfer_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.source.to_account_info(),
+                    to: ctx.accounts.protocol_fee_vault.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(transfer_ctx, protocol_fee)?;
+        }
 
         node.earnings_accumulated = node.earnings_accumulated.checked_add(operator_earnings).unwrap();
 
@@ -278,7 +1471,9 @@ pub mod node_registry {
 
         emit!(EarningsRecorded {
             operator: node.operator,
+            source: ctx.accounts.source.key(),
             amount: operator_earnings,
+            delegator_rewards,
             protocol_fee,
             timestamp: Clock::get()?.unix_timestamp,
         });
@@ -292,12 +1487,21 @@ pub mod node_registry {
 
         let amount = node.earnings_accumulated;
 
-        let transfer_ctx = CpiContext::new(
+        let node_key = node.key();
+        let bump = ctx.bumps.earnings_vault;
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"earnings",
+            node_key.as_ref(),
+            &[bump],
+        ]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
             ctx.accounts.system_program.to_account_info(),
             anchor_lang::system_program::Transfer {
                 from: ctx.accounts.earnings_vault.to_account_info(),
                 to: ctx.accounts.operator.to_account_info(),
             },
+            signer_seeds,
         );
         anchor_lang::system_program::transfer(transfer_ctx, amount)?;
 
@@ -323,14 +1527,17 @@ pub mod node_registry {
             timestamp: Clock::get()?.unix_timestamp,
         });
 
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
         Ok(())
     }
 
     pub fn reactivate_node(ctx: Context<ReactivateNode>) -> Result<()> {
         let node = &mut ctx.accounts.node_account;
         require!(!node.is_active, ErrorCode::NodeAlreadyActive);
-        require!(node.stake_amount >= MIN_STAKE, ErrorCode::InsufficientStake);
-        require!(node.reputation >= MIN_REPUTATION, ErrorCode::LowReputation);
+        let config = &ctx.accounts.global_registry.config;
+        require!(node.stake_amount >= config.min_stake, ErrorCode::InsufficientStake);
+        require!(node.reputation >= config.min_reputation, ErrorCode::LowReputation);
 
         node.is_active = true;
 
@@ -339,63 +1546,2328 @@ pub mod node_registry {
             timestamp: Clock::get()?.unix_timestamp,
         });
 
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + GlobalRegistry::LEN,
-        seeds = [b"registry"],
-        bump
-    )]
-    pub global_registry: Account<'info, GlobalRegistry>,
-    
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    /// CHECK: Protocol fee vault
-    pub protocol_fee_vault: AccountInfo<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
+    /// First step of an operator handover; `None` cancels a pending one.
+    pub fn transfer_operator(
+        ctx: Context<TransferOperator>,
+        new_operator: Option<Pubkey>,
+    ) -> Result<()> {
+        let node = &mut ctx.accounts.node_account;
+        if let Some(new_operator) = new_operator {
+            require_keys_neq!(new_operator, node.operator, ErrorCode::InvalidNewOperator);
+        }
+        node.pending_operator = new_operator;
 
-#[derive(Accounts)]
-pub struct RegisterNode<'info> {
-    #[account(
-        init,
-        payer = operator,
-        space = 8 + NodeAccount::LEN,
-        seeds = [b"node", operator.key().as_ref()],
-        bump
-    )]
-    pub node_account: Account<'info, NodeAccount>,
-    
-    #[account(
-        mut,
-        seeds = [b"registry"],
-        bump
-    )]
-    pub global_registry: Account<'info, GlobalRegistry>,
-    
-    #[account(mut)]
-    pub operator: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
+        emit!(OperatorTransferInitiated {
+            node_id: node.node_id,
+            operator: node.operator,
+            pending_operator: new_operator,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-#[derive(Accounts)]
-pub struct StakeSol<'info> {
-    #[account(
-        mut,
-        seeds = [b"node", operator.key().as_ref()],
-        bump,
-        has_one = operator
+        Ok(())
+    }
+
+    /// Completes the handover. Stake, unbonding tickets, delegations and
+    /// accrued earnings are keyed by the node account, so they move with it.
+    pub fn accept_operator(ctx: Context<AcceptOperator>) -> Result<()> {
+        let clock = Clock::get()?;
+        let node = &mut ctx.accounts.node_account;
+        require!(
+            node.pending_operator == Some(ctx.accounts.new_operator.key()),
+            ErrorCode::NoPendingOperatorTransfer
+        );
+
+        let previous_operator = node.operator;
+        node.operator = ctx.accounts.new_operator.key();
+        node.pending_operator = None;
+
+        let old_profile = &mut ctx.accounts.old_operator_profile;
+        old_profile.node_count = old_profile.node_count.checked_sub(1).unwrap();
+
+        let new_profile = &mut ctx.accounts.new_operator_profile;
+        if new_profile.operator == Pubkey::default() {
+            new_profile.operator = node.operator;
+            new_profile.created_at = clock.unix_timestamp;
+        }
+        new_profile.node_count = new_profile.node_count.checked_add(1).unwrap();
+
+        emit!(OperatorTransferred {
+            node_id: node.node_id,
+            previous_operator,
+            new_operator: node.operator,
+            timestamp: clock.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    pub fn set_commission(ctx: Context<SetCommission>, commission_bps: u16) -> Result<()> {
+        require!(commission_bps <= MAX_COMMISSION_BPS, ErrorCode::InvalidCommission);
+
+        let node = &mut ctx.accounts.node_account;
+        let old_commission_bps = node.commission_bps;
+        node.commission_bps = commission_bps;
+
+        emit!(CommissionUpdated {
+            operator: node.operator,
+            old_commission_bps,
+            new_commission_bps: commission_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn delegate_stake(ctx: Context<DelegateStake>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.global_registry.is_paused, ErrorCode::RegistryPaused);
+
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let node = &mut ctx.accounts.node_account;
+        require!(node.is_active, ErrorCode::NodeNotActive);
+
+        let delegation = &mut ctx.accounts.delegation;
+        if delegation.delegator == Pubkey::default() {
+            delegation.delegator = ctx.accounts.delegator.key();
+            delegation.node = node.key();
+            node.delegation_count = node.delegation_count.checked_add(1).unwrap();
+        }
+        settle_delegation_rewards(node, delegation);
+
+        let shares = if node.delegation_shares == 0 {
+            amount
+        } else {
+            require!(node.delegated_stake > 0, ErrorCode::DelegationPoolDepleted);
+            (amount as u128)
+                .checked_mul(node.delegation_shares as u128).unwrap()
+                .checked_div(node.delegated_stake as u128).unwrap() as u64
+        };
+        require!(shares > 0, ErrorCode::InvalidAmount);
+
+        match node.stake_mint {
+            None => {
+                let transfer_ctx = CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.delegator.to_account_info(),
+                        to: ctx.accounts.stake_vault.to_account_info(),
+                    },
+                );
+                anchor_lang::system_program::transfer(transfer_ctx, amount)?;
+            }
+            Some(_) => {
+                let (Some(stake_token_vault), Some(delegator_token_account), Some(token_program)) = (
+                    &ctx.accounts.stake_token_vault,
+                    &ctx.accounts.delegator_token_account,
+                    &ctx.accounts.token_program,
+                ) else {
+                    return err!(ErrorCode::MissingStakeTokenAccounts);
+                };
+                let transfer_ctx = CpiContext::new(
+                    token_program.to_account_info(),
+                    Transfer {
+                        from: delegator_token_account.to_account_info(),
+                        to: stake_token_vault.to_account_info(),
+                        authority: ctx.accounts.delegator.to_account_info(),
+                    },
+                );
+                token::transfer(transfer_ctx, amount)?;
+            }
+        }
+
+        node.delegated_stake = node.delegated_stake.checked_add(amount).unwrap();
+        node.delegation_shares = node.delegation_shares.checked_add(shares).unwrap();
+        delegation.shares = delegation.shares.checked_add(shares).unwrap();
+        delegation.reward_debt = reward_debt(node, delegation.shares);
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.total_stake = registry.total_stake.checked_add(amount).unwrap();
+
+        emit!(StakeDelegated {
+            delegator: delegation.delegator,
+            operator: node.operator,
+            amount,
+            shares,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    pub fn undelegate_stake(ctx: Context<UndelegateStake>, shares: u64) -> Result<()> {
+        let node = &mut ctx.accounts.node_account;
+        let delegation = &mut ctx.accounts.delegation;
+        require!(!node.pending_slash, ErrorCode::SlashProposalPending);
+        require!(shares > 0, ErrorCode::InvalidAmount);
+        require!(delegation.shares >= shares, ErrorCode::InsufficientBalance);
+
+        let clock = Clock::get()?;
+        let unbonding_until = clock
+            .unix_timestamp
+            .checked_add(ctx.accounts.global_registry.config.unbonding_period)
+            .unwrap();
+        let amount = unbond_delegation(node, delegation, shares, unbonding_until)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.total_stake = registry.total_stake.checked_sub(amount).unwrap();
+
+        emit!(StakeUndelegated {
+            delegator: delegation.delegator,
+            operator: node.operator,
+            amount,
+            shares,
+            unbonding_until: delegation.unbonding_until,
+            timestamp: clock.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_delegation(ctx: Context<WithdrawDelegation>) -> Result<()> {
+        let clock = Clock::get()?;
+        let node_key = ctx.accounts.node_account.key();
+        let node_operator = ctx.accounts.node_account.operator;
+
+        let node = &ctx.accounts.node_account;
+        let delegation = &mut ctx.accounts.delegation;
+        require!(delegation.unbonding_shares > 0, ErrorCode::NoUnbondingInProgress);
+        require!(
+            clock.unix_timestamp >= delegation.unbonding_until,
+            ErrorCode::UnbondingPeriodActive
+        );
+        require!(!node.pending_slash, ErrorCode::SlashProposalPending);
+
+        let shares = delegation.unbonding_shares;
+        let amount = redeem_unbonding_shares(
+            shares,
+            node.delegated_unbonding,
+            node.delegated_unbonding_shares,
+        );
+
+        match ctx.accounts.node_account.stake_mint {
+            None => {
+                let bump = ctx.bumps.stake_vault;
+                let signer_seeds: &[&[&[u8]]] = &[&[
+                    b"stake",
+                    node_key.as_ref(),
+                    &[bump],
+                ]];
+
+                let transfer_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.stake_vault.to_account_info(),
+                        to: ctx.accounts.delegator.to_account_info(),
+                    },
+                    signer_seeds,
+                );
+                anchor_lang::system_program::transfer(transfer_ctx, amount)?;
+            }
+            Some(_) => {
+                let (Some(stake_token_vault), Some(delegator_token_account), Some(token_program)) = (
+                    &ctx.accounts.stake_token_vault,
+                    &ctx.accounts.delegator_token_account,
+                    &ctx.accounts.token_program,
+                ) else {
+                    return err!(ErrorCode::MissingStakeTokenAccounts);
+                };
+                transfer_from_stake_token_vault(
+                    token_program,
+                    stake_token_vault,
+                    delegator_token_account.to_account_info(),
+                    &node_key,
+                    ctx.bumps.stake_token_vault.unwrap(),
+                    amount,
+                )?;
+            }
+        }
+
+        delegation.unbonding_shares = 0;
+        delegation.unbonding_until = 0;
+
+        let node = &mut ctx.accounts.node_account;
+        node.delegated_unbonding = node.delegated_unbonding.checked_sub(amount).unwrap();
+        node.delegated_unbonding_shares = node.delegated_unbonding_shares.checked_sub(shares).unwrap();
+
+        emit!(DelegationWithdrawn {
+            delegator: delegation.delegator,
+            operator: node_operator,
+            amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_delegator_rewards(ctx: Context<ClaimDelegatorRewards>) -> Result<()> {
+        let node = &ctx.accounts.node_account;
+        let delegation = &mut ctx.accounts.delegation;
+
+        settle_delegation_rewards(node, delegation);
+        delegation.reward_debt = reward_debt(node, delegation.shares);
+
+        let amount = delegation.pending_rewards;
+        require!(amount > 0, ErrorCode::NoEarningsToClaim);
+
+        let node_key = node.key();
+        let bump = ctx.bumps.earnings_vault;
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"earnings",
+            node_key.as_ref(),
+            &[bump],
+        ]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.earnings_vault.to_account_info(),
+                to: ctx.accounts.delegator.to_account_info(),
+            },
+            signer_seeds,
+        );
+        anchor_lang::system_program::transfer(transfer_ctx, amount)?;
+
+        delegation.pending_rewards = 0;
+
+        emit!(DelegatorRewardsClaimed {
+            delegator: delegation.delegator,
+            operator: node.operator,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_delegation(ctx: Context<CloseDelegation>) -> Result<()> {
+        let delegation = &ctx.accounts.delegation;
+        require!(
+            delegation.shares == 0
+                && delegation.unbonding_shares == 0
+                && delegation.pending_rewards == 0,
+            ErrorCode::DelegationNotEmpty
+        );
+
+        let node = &mut ctx.accounts.node_account;
+        node.delegation_count = node.delegation_count.checked_sub(1).unwrap();
+
+        Ok(())
+    }
+
+    /// Lets the operator of a deactivated node start unbonding a delegation
+    /// the delegator has left in place, so it cannot pin the node open.
+    pub fn force_undelegate(ctx: Context<ForceUndelegate>) -> Result<()> {
+        let node = &mut ctx.accounts.node_account;
+        let delegation = &mut ctx.accounts.delegation;
+        require!(!node.is_active, ErrorCode::NodeAlreadyActive);
+        require!(!node.pending_slash, ErrorCode::SlashProposalPending);
+
+        let shares = delegation.shares;
+        require!(shares > 0, ErrorCode::InvalidAmount);
+
+        let clock = Clock::get()?;
+        let unbonding_until = clock
+            .unix_timestamp
+            .checked_add(ctx.accounts.global_registry.config.unbonding_period)
+            .unwrap();
+        let amount = unbond_delegation(node, delegation, shares, unbonding_until)?;
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.total_stake = registry.total_stake.checked_sub(amount).unwrap();
+
+        emit!(StakeUndelegated {
+            delegator: delegation.delegator,
+            operator: node.operator,
+            amount,
+            shares,
+            unbonding_until: delegation.unbonding_until,
+            timestamp: clock.unix_timestamp,
+        });
+
+        sync_directory_entry(&ctx.accounts.directory_page, &ctx.accounts.node_account)?;
+
+        Ok(())
+    }
+
+    /// Pays out a fully unbonded delegation on a deactivated node to its
+    /// delegator and closes it, refunding the rent to the delegator.
+    pub fn evict_delegation(ctx: Context<EvictDelegation>) -> Result<()> {
+        let clock = Clock::get()?;
+        let node_key = ctx.accounts.node_account.key();
+        let node = &ctx.accounts.node_account;
+        let delegation = &mut ctx.accounts.delegation;
+        require!(!node.is_active, ErrorCode::NodeAlreadyActive);
+        require!(!node.pending_slash, ErrorCode::SlashProposalPending);
+        require!(delegation.shares == 0, ErrorCode::DelegationNotEmpty);
+        require!(
+            clock.unix_timestamp >= delegation.unbonding_until,
+            ErrorCode::UnbondingPeriodActive
+        );
+
+        settle_delegation_rewards(node, delegation);
+        let unbonding_shares = delegation.unbonding_shares;
+        let amount = redeem_unbonding_shares(
+            unbonding_shares,
+            node.delegated_unbonding,
+            node.delegated_unbonding_shares,
+        );
+        let rewards = delegation.pending_rewards;
+
+        if amount > 0 {
+            match node.stake_mint {
+                None => {
+                    transfer_from_stake_vault(
+                        &ctx.accounts.system_program,
+                        &ctx.accounts.stake_vault,
+                        ctx.accounts.delegator.to_account_info(),
+                        &node_key,
+                        ctx.bumps.stake_vault,
+                        amount,
+                    )?;
+                }
+                Some(_) => {
+                    let (Some(stake_token_vault), Some(delegator_token_account), Some(token_program)) = (
+                        &ctx.accounts.stake_token_vault,
+                        &ctx.accounts.delegator_token_account,
+                        &ctx.accounts.token_program,
+                    ) else {
+                        return err!(ErrorCode::MissingStakeTokenAccounts);
+                    };
+                    require_keys_eq!(
+                        delegator_token_account.owner,
+                        delegation.delegator,
+                        ErrorCode::InvalidDelegatorTokenAccount
+                    );
+                    transfer_from_stake_token_vault(
+                        token_program,
+                        stake_token_vault,
+                        delegator_token_account.to_account_info(),
+                        &node_key,
+                        ctx.bumps.stake_token_vault.unwrap(),
+                        amount,
+                    )?;
+                }
+            }
+        }
+
+        if rewards > 0 {
+            let bump = ctx.bumps.earnings_vault;
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                b"earnings",
+                node_key.as_ref(),
+                &[bump],
+            ]];
+
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.earnings_vault.to_account_info(),
+                    to: ctx.accounts.delegator.to_account_info(),
+                },
+                signer_seeds,
+            );
+            anchor_lang::system_program::transfer(transfer_ctx, rewards)?;
+        }
+
+        delegation.unbonding_shares = 0;
+        delegation.pending_rewards = 0;
+
+        let delegator = delegation.delegator;
+        let node = &mut ctx.accounts.node_account;
+        node.delegated_unbonding = node.delegated_unbonding.checked_sub(amount).unwrap();
+        node.delegated_unbonding_shares = node
+            .delegated_unbonding_shares
+            .checked_sub(unbonding_shares).unwrap();
+        node.delegation_count = node.delegation_count.checked_sub(1).unwrap();
+
+        emit!(DelegationEvicted {
+            delegator,
+            operator: node.operator,
+            amount,
+            rewards,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a node that has no stake, delegations, unbonding tickets,
+    /// earnings or slash proposal left, returning its rent and vault
+    /// reserves to the operator.
+    pub fn deregister_node(ctx: Context<DeregisterNode>) -> Result<()> {
+        let node = &ctx.accounts.node_account;
+        require!(
+            node.stake_amount == 0 && node.open_tickets == 0 && node.unbonding_amount == 0,
+            ErrorCode::StakeOutstanding
+        );
+        require!(node.delegation_count == 0, ErrorCode::DelegationsOutstanding);
+        require!(node.earnings_accumulated == 0, ErrorCode::EarningsOutstanding);
+        require!(node.open_epoch_points == 0, ErrorCode::EpochPointsOutstanding);
+        require!(!node.pending_slash, ErrorCode::SlashProposalPending);
+
+        let node_key = node.key();
+        let node_id = node.node_id;
+        let operator = node.operator;
+
+        let earnings_balance = ctx.accounts.earnings_vault.lamports();
+        if earnings_balance > 0 {
+            let bump = ctx.bumps.earnings_vault;
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                b"earnings",
+                node_key.as_ref(),
+                &[bump],
+            ]];
+
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.earnings_vault.to_account_info(),
+                    to: ctx.accounts.operator.to_account_info(),
+                },
+                signer_seeds,
+            );
+            anchor_lang::system_program::transfer(transfer_ctx, earnings_balance)?;
+        }
+
+        // Share rounding can leave dust behind; it goes out with the vault.
+        let stake_balance = ctx.accounts.stake_vault.lamports();
+        if stake_balance > 0 {
+            transfer_from_stake_vault(
+                &ctx.accounts.system_program,
+                &ctx.accounts.stake_vault,
+                ctx.accounts.operator.to_account_info(),
+                &node_key,
+                ctx.bumps.stake_vault,
+                stake_balance,
+            )?;
+        }
+
+        if node.stake_mint.is_some() {
+            let (Some(stake_token_vault), Some(operator_token_account), Some(token_program)) = (
+                &ctx.accounts.stake_token_vault,
+                &ctx.accounts.operator_token_account,
+                &ctx.accounts.token_program,
+            ) else {
+                return err!(ErrorCode::MissingStakeTokenAccounts);
+            };
+            let bump = ctx.bumps.stake_token_vault.unwrap();
+            if stake_token_vault.amount > 0 {
+                transfer_from_stake_token_vault(
+                    token_program,
+                    stake_token_vault,
+                    operator_token_account.to_account_info(),
+                    &node_key,
+                    bump,
+                    stake_token_vault.amount,
+                )?;
+            }
+
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                b"stake_token",
+                node_key.as_ref(),
+                &[bump],
+            ]];
+            let close_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                CloseAccount {
+                    account: stake_token_vault.to_account_info(),
+                    destination: ctx.accounts.operator.to_account_info(),
+                    authority: stake_token_vault.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token::close_account(close_ctx)?;
+        }
+
+        // Slots are not reused; readers skip zeroed entries.
+        {
+            let page = &mut ctx.accounts.directory_page.load_mut()?;
+            let entry = &mut page.entries[node.directory_slot as usize];
+            require_keys_eq!(entry.node, node_key, ErrorCode::DirectoryEntryMismatch);
+            *entry = DirectoryEntry::default();
+        }
+
+        let profile = &mut ctx.accounts.operator_profile;
+        profile.node_count = profile.node_count.checked_sub(1).unwrap();
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.total_nodes = registry.total_nodes.checked_sub(1).unwrap();
+
+        emit!(NodeDeregistered {
+            node_id,
+            operator,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn fund_rewards_pool(ctx: Context<FundRewardsPool>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.funder.to_account_info(),
+                to: ctx.accounts.rewards_pool.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(transfer_ctx, amount)?;
+
+        emit!(RewardsPoolFunded {
+            funder: ctx.accounts.funder.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless: closes the current epoch once `epoch_duration` has
+    /// elapsed and reserves up to `epoch_emission` from the rewards pool for
+    /// it. Epochs with no points emit nothing, leaving the budget in the pool.
+    pub fn crank_epoch(ctx: Context<CrankEpoch>) -> Result<()> {
+        let clock = Clock::get()?;
+        let registry = &mut ctx.accounts.global_registry;
+        require!(
+            clock.unix_timestamp
+                >= registry.epoch_started_at.checked_add(registry.config.epoch_duration).unwrap(),
+            ErrorCode::EpochNotEnded
+        );
+
+        let pool_balance = ctx.accounts.rewards_pool.lamports();
+        let available = pool_balance
+            .saturating_sub(Rent::get()?.minimum_balance(0))
+            .saturating_sub(registry.rewards_reserved);
+        let emission = if registry.epoch_total_points == 0 {
+            0
+        } else {
+            registry.config.epoch_emission.min(available)
+        };
+
+        let reward_epoch = &mut ctx.accounts.reward_epoch;
+        reward_epoch.epoch = registry.current_epoch;
+        reward_epoch.total_points = registry.epoch_total_points;
+        reward_epoch.emission = emission;
+        reward_epoch.claimed = 0;
+        reward_epoch.finalized_at = clock.unix_timestamp;
+
+        registry.rewards_reserved = registry.rewards_reserved.checked_add(emission).unwrap();
+        registry.current_epoch = registry.current_epoch.checked_add(1).unwrap();
+        registry.epoch_started_at = clock.unix_timestamp;
+        registry.epoch_total_points = 0;
+
+        emit!(EpochFinalized {
+            epoch: reward_epoch.epoch,
+            total_points: reward_epoch.total_points,
+            emission,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pays the node's pro-rata share of a finalized epoch's emission and
+    /// closes its points account.
+    pub fn claim_epoch_rewards(ctx: Context<ClaimEpochRewards>) -> Result<()> {
+        let reward_epoch = &mut ctx.accounts.reward_epoch;
+        let epoch_points = &ctx.accounts.node_epoch_points;
+
+        let reward = if reward_epoch.total_points == 0 {
+            0
+        } else {
+            (reward_epoch.emission as u128)
+                .checked_mul(epoch_points.points).unwrap()
+                .checked_div(reward_epoch.total_points).unwrap() as u64
+        };
+
+        if reward > 0 {
+            let bump = ctx.bumps.rewards_pool;
+            let signer_seeds: &[&[&[u8]]] = &[&[b"rewards_pool", &[bump]]];
+
+            let transfer_ctx = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.rewards_pool.to_account_info(),
+                    to: ctx.accounts.operator.to_account_info(),
+                },
+                signer_seeds,
+            );
+            anchor_lang::system_program::transfer(transfer_ctx, reward)?;
+        }
+
+        reward_epoch.claimed = reward_epoch.claimed.checked_add(reward).unwrap();
+
+        let registry = &mut ctx.accounts.global_registry;
+        registry.rewards_reserved = registry.rewards_reserved.checked_sub(reward).unwrap();
+
+        let node = &mut ctx.accounts.node_account;
+        node.open_epoch_points = node.open_epoch_points.checked_sub(1).unwrap();
+
+        emit!(EpochRewardsClaimed {
+            operator: ctx.accounts.operator.key(),
+            epoch: reward_epoch.epoch,
+            points: epoch_points.points,
+            amount: reward,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless: once `reward_claim_period` has passed, releases an
+    /// epoch's unclaimed emission and rounding dust back to the rewards pool
+    /// and closes the epoch into it.
+    pub fn sweep_reward_epoch(ctx: Context<SweepRewardEpoch>) -> Result<()> {
+        let clock = Clock::get()?;
+        let registry = &mut ctx.accounts.global_registry;
+        let reward_epoch = &ctx.accounts.reward_epoch;
+        require!(
+            clock.unix_timestamp
+                >= reward_epoch.finalized_at.checked_add(registry.config.reward_claim_period).unwrap(),
+            ErrorCode::ClaimPeriodActive
+        );
+
+        let unclaimed = reward_epoch.emission.checked_sub(reward_epoch.claimed).unwrap();
+        registry.rewards_reserved = registry.rewards_reserved.checked_sub(unclaimed).unwrap();
+
+        emit!(RewardEpochSwept {
+            epoch: reward_epoch.epoch,
+            unclaimed,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless: closes a points account whose epoch has been swept, so
+    /// it no longer blocks the node from deregistering.
+    pub fn expire_epoch_points(ctx: Context<ExpireEpochPoints>) -> Result<()> {
+        let node = &mut ctx.accounts.node_account;
+        node.open_epoch_points = node.open_epoch_points.checked_sub(1).unwrap();
+
+        emit!(EpochPointsExpired {
+            node_id: node.node_id,
+            epoch: ctx.accounts.node_epoch_points.epoch,
+            points: ctx.accounts.node_epoch_points.points,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+/// Requires `multisig_threshold` distinct registered approvers to have signed,
+/// passed as remaining accounts. A no-op while no multisig is configured.
+fn require_multisig(registry: &GlobalRegistry, approvers: &[AccountInfo]) -> Result<()> {
+    if registry.multisig_threshold == 0 {
+        return Ok(());
+    }
+
+    let mut approved: Vec<Pubkey> = Vec::with_capacity(approvers.len());
+    for info in approvers {
+        if info.is_signer
+            && registry.multisig_signers.contains(info.key)
+            && !approved.contains(info.key)
+        {
+            approved.push(info.key());
+        }
+    }
+    require!(
+        approved.len() >= registry.multisig_threshold as usize,
+        ErrorCode::InsufficientApprovals
+    );
+    Ok(())
+}
+
+/// Reads the served user and circuit from a vrf-selection `VrfRequest`.
+fn load_vrf_request(info: &AccountInfo) -> Result<VrfRequestPrefix> {
+    let data = info.try_borrow_data()?;
+    let discriminator = anchor_lang::solana_program::hash::hash(b"account:VrfRequest");
+    require!(
+        data.len() > 8 && data[..8] == discriminator.to_bytes()[..8],
+        ErrorCode::UnprovenReporter
+    );
+    VrfRequestPrefix::deserialize(&mut &data[8..]).map_err(|_| error!(ErrorCode::UnprovenReporter))
+}
+
+/// Refreshes the node's directory entry from its current account state.
+fn sync_directory_entry(
+    directory_page: &AccountLoader<DirectoryPage>,
+    node: &Account<NodeAccount>,
+) -> Result<()> {
+    let page = &mut directory_page.load_mut()?;
+    let entry = &mut page.entries[node.directory_slot as usize];
+    require_keys_eq!(entry.node, node.key(), ErrorCode::DirectoryEntryMismatch);
+    *entry = DirectoryEntry::from_node(node.key(), node);
+    Ok(())
+}
+
+/// Rolls the node's heartbeat bitmap forward to the current epoch, marking
+/// it as seen when `heartbeat` is set, and recomputes `uptime_percentage`
+/// over the epochs tracked so far (at most `UPTIME_WINDOW_EPOCHS`).
+fn record_uptime(node: &mut NodeAccount, now: i64, heartbeat: bool) {
+    let epoch = now.checked_div(UPTIME_EPOCH_SECONDS).unwrap();
+    let elapsed = epoch.checked_sub(node.uptime_epoch).unwrap().max(0);
+    node.uptime_bitmap = if elapsed >= UPTIME_WINDOW_EPOCHS {
+        0
+    } else {
+        node.uptime_bitmap.checked_shl(elapsed as u32).unwrap()
+    };
+    node.uptime_epoch = node.uptime_epoch.max(epoch);
+    if heartbeat {
+        node.uptime_bitmap |= 1;
+    }
+
+    let first_epoch = node.registered_at.checked_div(UPTIME_EPOCH_SECONDS).unwrap();
+    let tracked = node
+        .uptime_epoch
+        .checked_sub(first_epoch).unwrap()
+        .checked_add(1).unwrap()
+        .min(UPTIME_WINDOW_EPOCHS);
+    node.uptime_percentage = (node.uptime_bitmap.count_ones() as i64)
+        .checked_mul(100).unwrap()
+        .checked_div(tracked).unwrap()
+        .min(100) as u8;
+}
+
+/// Adds `points` to the node's `NodeEpochPoints` for `epoch`, creating the
+/// account on the first credit so heartbeats that earn nothing leave no
+/// rent-paying account behind. Returns whether the account was created.
+fn credit_epoch_points<'info>(
+    epoch_points: &UncheckedAccount<'info>,
+    operator: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    node_key: Pubkey,
+    epoch: u64,
+    bump: u8,
+    points: u128,
+) -> Result<bool> {
+    let info = epoch_points.to_account_info();
+    if info.owner == &crate::ID {
+        let mut data = info.try_borrow_mut_data()?;
+        let mut account = NodeEpochPoints::try_deserialize(&mut &data[..])?;
+        require_keys_eq!(account.node, node_key, ErrorCode::InvalidEpochPoints);
+        account.points = account.points.checked_add(points).unwrap();
+        account.try_serialize(&mut &mut data[..])?;
+        return Ok(false);
+    }
+
+    let space = NodeEpochPoints::LEN.checked_add(8).unwrap();
+    let epoch_bytes = epoch.to_le_bytes();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"node_epoch",
+        node_key.as_ref(),
+        epoch_bytes.as_ref(),
+        &[bump],
+    ]];
+
+    // Top up rather than create, as Anchor's `init` does, so lamports sent
+    // to the address ahead of time cannot block it.
+    let shortfall = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(info.lamports());
+    if shortfall > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: operator.to_account_info(),
+                    to: info.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+    anchor_lang::system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Allocate { account_to_allocate: info.clone() },
+            signer_seeds,
+        ),
+        space as u64,
+    )?;
+    anchor_lang::system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Assign { account_to_assign: info.clone() },
+            signer_seeds,
+        ),
+        &crate::ID,
+    )?;
+
+    let account = NodeEpochPoints { node: node_key, epoch, points };
+    account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok(true)
+}
+
+fn apply_pending_bandwidth(node: &mut NodeAccount, current_epoch: u64) {
+    if node.pending_bandwidth_gbps != 0 && current_epoch >= node.bandwidth_effective_epoch {
+        node.bandwidth_gbps = node.pending_bandwidth_gbps;
+        node.pending_bandwidth_gbps = 0;
+    }
+}
+
+/// Number of whole decay periods since `since`, capped so shifts stay in range.
+fn decay_halvings(since: i64, now: i64) -> u32 {
+    if since == 0 {
+        return 0;
+    }
+    now.checked_sub(since).unwrap()
+        .max(0)
+        .checked_div(REPUTATION_DECAY_PERIOD).unwrap()
+        .min(15) as u32
+}
+
+fn decayed_adjustment(node: &NodeAccount, now: i64) -> i16 {
+    // 2^14 already exceeds any adjustment, and 2^15 would overflow i16.
+    let halvings = decay_halvings(node.adjustment_at, now).min(14);
+    node.reputation_adjustment.checked_div(1i16.checked_shl(halvings).unwrap()).unwrap()
+}
+
+/// Adds `delta` to the decayed adjustment, bounded by `MAX_REPUTATION_ADJUSTMENT`.
+fn next_adjustment(node: &NodeAccount, now: i64, delta: i16) -> i16 {
+    decayed_adjustment(node, now)
+        .checked_add(delta).unwrap()
+        .clamp(-MAX_REPUTATION_ADJUSTMENT, MAX_REPUTATION_ADJUSTMENT)
+}
+
+/// Scores each reputation input on 0-100 and blends them as uptime 35%,
+/// slash history 20%, quality reports 20%, bandwidth served 15% and stake
+/// age 10%, then applies the decayed authority adjustment. Slash penalties
+/// and adjustments halve every `REPUTATION_DECAY_PERIOD`.
+fn compute_reputation(node: &NodeAccount, now: i64) -> ReputationBreakdown {
+    let uptime = node.uptime_percentage.min(100);
+
+    let penalty = (node.slash_count as u64)
+        .checked_mul(SLASH_REPUTATION_PENALTY).unwrap()
+        .min(100)
+        .checked_shr(decay_halvings(node.last_slashed_at, now)).unwrap();
+    let slashing = 100u64.checked_sub(penalty).unwrap() as u8;
+
+    // Nodes without reports are treated as neutral rather than penalised.
+    let quality = if node.quality_report_count == 0 {
+        100
+    } else {
+        node.quality_score_total
+            .checked_div(node.quality_report_count as u64).unwrap()
+            .min(100) as u8
+    };
+
+    let bandwidth = node.total_bandwidth_served
+        .min(FULL_BANDWIDTH_SCORE_GB)
+        .checked_mul(100).unwrap()
+        .checked_div(FULL_BANDWIDTH_SCORE_GB).unwrap() as u8;
+
+    let stake_age = now.checked_sub(node.registered_at).unwrap()
+        .clamp(0, FULL_STAKE_AGE_SCORE)
+        .checked_mul(100).unwrap()
+        .checked_div(FULL_STAKE_AGE_SCORE).unwrap() as u8;
+
+    let blended = (uptime as u64).checked_mul(35).unwrap()
+        .checked_add((slashing as u64).checked_mul(20).unwrap()).unwrap()
+        .checked_add((quality as u64).checked_mul(20).unwrap()).unwrap()
+        .checked_add((bandwidth as u64).checked_mul(15).unwrap()).unwrap()
+        .checked_add((stake_age as u64).checked_mul(10).unwrap()).unwrap()
+        .checked_div(100).unwrap() as i16;
+
+    let adjustment = decayed_adjustment(node, now);
+    let score = blended.checked_add(adjustment).unwrap().clamp(0, 100) as u8;
+
+    ReputationBreakdown {
+        score,
+        uptime,
+        slashing,
+        quality,
+        bandwidth,
+        stake_age,
+        adjustment,
+    }
+}
+
+/// Recomputes the node's reputation and emits `ReputationUpdated` with the
+/// inputs whenever the score changes or the authority made an adjustment.
+fn refresh_reputation(
+    node: &mut NodeAccount,
+    now: i64,
+    trigger: ReputationTrigger,
+    reason_code: u16,
+) {
+    let breakdown = compute_reputation(node, now);
+    let old_score = node.reputation;
+    node.reputation = breakdown.score;
+
+    if old_score != breakdown.score || trigger == ReputationTrigger::Adjustment {
+        emit!(ReputationUpdated {
+            operator: node.operator,
+            old_score,
+            new_score: breakdown.score,
+            trigger,
+            reason_code,
+            breakdown,
+            timestamp: now,
+        });
+    }
+}
+
+fn transfer_from_stake_vault<'info>(
+    system_program: &Program<'info, System>,
+    stake_vault: &SystemAccount<'info>,
+    to: AccountInfo<'info>,
+    node_key: &Pubkey,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"stake",
+        node_key.as_ref(),
+        &[bump],
+    ]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        system_program.to_account_info(),
+        anchor_lang::system_program::Transfer {
+            from: stake_vault.to_account_info(),
+            to,
+        },
+        signer_seeds,
+    );
+    anchor_lang::system_program::transfer(transfer_ctx, amount)
+}
+
+fn transfer_from_stake_token_vault<'info>(
+    token_program: &Program<'info, Token>,
+    stake_token_vault: &Account<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    node_key: &Pubkey,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"stake_token",
+        node_key.as_ref(),
+        &[bump],
+    ]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        Transfer {
+            from: stake_token_vault.to_account_info(),
+            to,
+            authority: stake_token_vault.to_account_info(),
+        },
+        signer_seeds,
+    );
+    token::transfer(transfer_ctx, amount)
+}
+
+/// Stake taken by one slash, by where it was held.
+struct SlashAmounts {
+    stake: u64,
+    delegated: u64,
+    unbonding: u64,
+    delegated_unbonding: u64,
+}
+
+impl SlashAmounts {
+    /// The part still counted in `GlobalRegistry::total_stake`.
+    fn bonded(&self) -> u64 {
+        self.stake.checked_add(self.delegated).unwrap()
+    }
+
+    fn total(&self) -> u64 {
+        self.bonded()
+            .checked_add(self.unbonding).unwrap()
+            .checked_add(self.delegated_unbonding).unwrap()
+    }
+}
+
+/// Takes `slash_bps` of the node's bonded and unbonding stake. Delegators and
+/// unbonding stake share the penalty at the same rate; their shares stay put
+/// while the stake backing them shrinks.
+fn apply_slash(node: &mut NodeAccount, slash_bps: u16) -> SlashAmounts {
+    let portion = |amount: u64| {
+        amount
+            .checked_mul(slash_bps as u64).unwrap()
+            .checked_div(10000).unwrap()
+    };
+    let slashed = SlashAmounts {
+        stake: portion(node.stake_amount),
+        delegated: portion(node.delegated_stake),
+        unbonding: portion(node.unbonding_amount),
+        delegated_unbonding: portion(node.delegated_unbonding),
+    };
+
+    node.stake_amount = node.stake_amount.checked_sub(slashed.stake).unwrap();
+    node.delegated_stake = node.delegated_stake.checked_sub(slashed.delegated).unwrap();
+    node.unbonding_amount = node.unbonding_amount.checked_sub(slashed.unbonding).unwrap();
+    node.delegated_unbonding = node
+        .delegated_unbonding
+        .checked_sub(slashed.delegated_unbonding).unwrap();
+    slashed
+}
+
+/// Shares worth `amount` in an unbonding pool holding `pool_amount` stake.
+fn issue_unbonding_shares(amount: u64, pool_amount: u64, pool_shares: u64) -> Result<u64> {
+    if pool_shares == 0 {
+        return Ok(amount);
+    }
+    require!(pool_amount > 0, ErrorCode::UnbondingPoolDepleted);
+    Ok((amount as u128)
+        .checked_mul(pool_shares as u128).unwrap()
+        .checked_div(pool_amount as u128).unwrap() as u64)
+}
+
+/// Stake backing `shares`; the last shares out take any rounding dust.
+fn redeem_unbonding_shares(shares: u64, pool_amount: u64, pool_shares: u64) -> u64 {
+    if shares == pool_shares {
+        return pool_amount;
+    }
+    (shares as u128)
+        .checked_mul(pool_amount as u128).unwrap()
+        .checked_div(pool_shares as u128).unwrap() as u64
+}
+
+fn reward_debt(node: &NodeAccount, shares: u64) -> u128 {
+    (shares as u128)
+        .checked_mul(node.acc_reward_per_share).unwrap()
+        .checked_div(REWARD_PRECISION).unwrap()
+}
+
+/// Moves `shares` of a delegation into unbonding at the current share price
+/// and returns the stake they were worth.
+fn unbond_delegation(
+    node: &mut NodeAccount,
+    delegation: &mut Delegation,
+    shares: u64,
+    unbonding_until: i64,
+) -> Result<u64> {
+    settle_delegation_rewards(node, delegation);
+
+    let amount = (shares as u128)
+        .checked_mul(node.delegated_stake as u128).unwrap()
+        .checked_div(node.delegation_shares as u128).unwrap() as u64;
+
+    node.delegated_stake = node.delegated_stake.checked_sub(amount).unwrap();
+    node.delegation_shares = node.delegation_shares.checked_sub(shares).unwrap();
+    delegation.shares = delegation.shares.checked_sub(shares).unwrap();
+    delegation.reward_debt = reward_debt(node, delegation.shares);
+
+    let unbonding_shares = issue_unbonding_shares(
+        amount,
+        node.delegated_unbonding,
+        node.delegated_unbonding_shares,
+    )?;
+    node.delegated_unbonding = node.delegated_unbonding.checked_add(amount).unwrap();
+    node.delegated_unbonding_shares = node
+        .delegated_unbonding_shares
+        .checked_add(unbonding_shares).unwrap();
+    delegation.unbonding_shares = delegation.unbonding_shares.checked_add(unbonding_shares).unwrap();
+    delegation.unbonding_until = unbonding_until;
+    Ok(amount)
+}
+
+/// Moves rewards accrued on the delegation's current shares into
+/// `pending_rewards`. Callers must reset `reward_debt` after changing shares.
+fn settle_delegation_rewards(node: &NodeAccount, delegation: &mut Delegation) {
+    let accrued = reward_debt(node, delegation.shares)
+        .checked_sub(delegation.reward_debt).unwrap();
+    delegation.pending_rewards = delegation
+        .pending_rewards
+        .checked_add(u64::try_from(accrued).unwrap())
+        .unwrap();
+    delegation.reward_debt = reward_debt(node, delegation.shares);
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + GlobalRegistry::LEN,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    /// CHECK: Protocol fee vault
+    pub protocol_fee_vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageEarningsSources<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = authority
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageArbiters<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = authority
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = authority
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = authority
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageRegistry<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = authority
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateDirectoryPage<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + DirectoryPage::LEN,
+        seeds = [b"directory", global_registry.directory_pages.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetStakeMint<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = authority
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterNode<'info> {
+    #[account(
+        init,
+        payer = operator,
+        space = 8 + NodeAccount::LEN,
+        seeds = [b"node", node_identity.key().as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = operator,
+        space = 8 + OperatorProfile::LEN,
+        seeds = [b"operator", operator.key().as_ref()],
+        bump
+    )]
+    pub operator_profile: Account<'info, OperatorProfile>,
+    
+    /// Node identity key; the node PDA is derived from it so it survives
+    /// operator rotation.
+    pub node_identity: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"earnings", node_account.key().as_ref()],
+        bump
+    )]
+    pub earnings_vault: SystemAccount<'info>,
+    
+    #[account(
+        init,
+        payer = operator,
+        token::mint = stake_mint,
+        token::authority = stake_token_vault,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    pub stake_mint: Option<Account<'info, Mint>>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", global_registry.next_directory_page().to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeSol<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeTokens<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub operator_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub operator: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeSol<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        init,
+        payer = operator,
+        space = 8 + UnbondingTicket::LEN,
+        seeds = [
+            b"unbond",
+            node_account.key().as_ref(),
+            node_account.next_ticket_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub unbonding_ticket: Account<'info, UnbondingTicket>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawUnstaked<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [
+            b"unbond",
+            node_account.key().as_ref(),
+            unbonding_ticket.ticket_id.to_le_bytes().as_ref()
+        ],
+        bump,
+        constraint = unbonding_ticket.node == node_account.key() @ ErrorCode::NoUnbondingInProgress,
+        close = operator
+    )]
+    pub unbonding_ticket: Account<'info, UnbondingTicket>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub operator_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelUnbonding<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [
+            b"unbond",
+            node_account.key().as_ref(),
+            unbonding_ticket.ticket_id.to_le_bytes().as_ref()
+        ],
+        bump,
+        constraint = unbonding_ticket.node == node_account.key() @ ErrorCode::NoUnbondingInProgress,
+        close = operator
+    )]
+    pub unbonding_ticket: Account<'info, UnbondingTicket>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateHeartbeat<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    /// CHECK: this epoch's `NodeEpochPoints` address; created by
+    /// `credit_epoch_points` on the first heartbeat that earns points.
+    #[account(
+        mut,
+        seeds = [
+            b"node_epoch",
+            node_account.key().as_ref(),
+            global_registry.current_epoch.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub node_epoch_points: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdjustReputation<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump,
+        has_one = authority
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitQualityReport<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        init_if_needed,
+        payer = reporter,
+        space = 8 + QualityReport::LEN,
+        seeds = [b"quality_report", node_account.key().as_ref(), reporter.key().as_ref()],
+        bump
+    )]
+    pub quality_report: Account<'info, QualityReport>,
+    
+    /// CHECK: Owner and discriminator are checked in `load_vrf_request`
+    #[account(owner = VRF_SELECTION_PROGRAM_ID)]
+    pub vrf_request: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub reporter: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshNodeReputation<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+}
+
+#[derive(Accounts)]
+pub struct SlashNode<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = authority,
+        has_one = protocol_fee_vault
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    /// CHECK: Protocol fee vault
+    #[account(mut)]
+    pub protocol_fee_vault: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub protocol_fee_token_account: Option<Account<'info, TokenAccount>>,
+    
+    pub authority: Signer<'info>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReportDowntime<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = protocol_fee_vault
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    /// CHECK: Protocol fee vault
+    #[account(mut)]
+    pub protocol_fee_vault: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub reporter_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub protocol_fee_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub reporter: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeSlash<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        init,
+        payer = reporter,
+        space = 8 + SlashProposal::LEN,
+        seeds = [b"slash_proposal", node_account.key().as_ref()],
+        bump
+    )]
+    pub slash_proposal: Account<'info, SlashProposal>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub reporter: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisputeSlash<'info> {
+    #[account(
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"slash_proposal", node_account.key().as_ref()],
+        bump
+    )]
+    pub slash_proposal: Account<'info, SlashProposal>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveSlash<'info> {
+    #[account(
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"slash_proposal", node_account.key().as_ref()],
+        bump
+    )]
+    pub slash_proposal: Account<'info, SlashProposal>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub arbiter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSlash<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"slash_proposal", node_account.key().as_ref()],
+        bump,
+        has_one = reporter,
+        close = reporter
+    )]
+    pub slash_proposal: Account<'info, SlashProposal>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = protocol_fee_vault
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    /// CHECK: Protocol fee vault
+    #[account(mut)]
+    pub protocol_fee_vault: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub protocol_fee_token_account: Option<Account<'info, TokenAccount>>,
+    
+    /// CHECK: Receives both bonds on an upheld slash; checked against the proposal
+    #[account(mut)]
+    pub reporter: AccountInfo<'info>,
+    
+    /// CHECK: Receives both bonds on a rejected slash; checked against the node
+    #[account(mut, address = node_account.operator)]
+    pub operator: AccountInfo<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RecordEarnings<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"earnings", node_account.key().as_ref()],
+        bump
+    )]
+    pub earnings_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump,
+        has_one = protocol_fee_vault
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    /// CHECK: Protocol fee vault
+    #[account(mut)]
+    pub protocol_fee_vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub source: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimEarnings<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"earnings", node_account.key().as_ref()],
+        bump
+    )]
+    pub earnings_vault: SystemAccount<'info>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DeactivateNode<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReactivateNode<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNodeMetadata<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TransferOperator<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOperator<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"operator", node_account.operator.as_ref()],
+        bump
+    )]
+    pub old_operator_profile: Account<'info, OperatorProfile>,
+    
+    #[account(
+        init_if_needed,
+        payer = new_operator,
+        space = 8 + OperatorProfile::LEN,
+        seeds = [b"operator", new_operator.key().as_ref()],
+        bump
+    )]
+    pub new_operator_profile: Account<'info, OperatorProfile>,
+    
+    #[account(mut)]
+    pub new_operator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetCommission<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DelegateStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        init_if_needed,
+        payer = delegator,
+        space = 8 + Delegation::LEN,
+        seeds = [b"delegation", node_account.key().as_ref(), delegator.key().as_ref()],
+        bump
+    )]
+    pub delegation: Account<'info, Delegation>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub delegator_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UndelegateStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"delegation", node_account.key().as_ref(), delegator.key().as_ref()],
+        bump,
+        has_one = delegator
+    )]
+    pub delegation: Account<'info, Delegation>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub delegator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawDelegation<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"delegation", node_account.key().as_ref(), delegator.key().as_ref()],
+        bump,
+        has_one = delegator
+    )]
+    pub delegation: Account<'info, Delegation>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_token", node_account.key().as_ref()],
+        bump
+    )]
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub delegator_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimDelegatorRewards<'info> {
+    #[account(
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"delegation", node_account.key().as_ref(), delegator.key().as_ref()],
+        bump,
+        has_one = delegator
+    )]
+    pub delegation: Account<'info, Delegation>,
+    
+    #[account(
+        mut,
+        seeds = [b"earnings", node_account.key().as_ref()],
+        bump
+    )]
+    pub earnings_vault: SystemAccount<'info>,
+    
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ForceUndelegate<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
+    
+    #[account(
+        mut,
+        seeds = [b"delegation", node_account.key().as_ref(), delegation.delegator.as_ref()],
+        bump
+    )]
+    pub delegation: Account<'info, Delegation>,
+    
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EvictDelegation<'info> {
+    #[account(
+        mut,
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump,
+        has_one = operator
+    )]
+    pub node_account: Account<'info, NodeAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"delegation", node_account.key().as_ref(), delegator.key().as_ref()],
+        bump,
+        has_one = delegator,
+        close = delegator
     )]
-    pub node_account: Account<'info, NodeAccount>,
+    pub delegation: Account<'info, Delegation>,
     
     #[account(
         mut,
@@ -406,41 +3878,77 @@ pub struct StakeSol<'info> {
     
     #[account(
         mut,
-        seeds = [b"registry"],
+        seeds = [b"stake_token", node_account.key().as_ref()],
         bump
     )]
-    pub global_registry: Account<'info, GlobalRegistry>,
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"earnings", node_account.key().as_ref()],
+        bump
+    )]
+    pub earnings_vault: SystemAccount<'info>,
     
     #[account(mut)]
+    pub delegator_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub delegator: SystemAccount<'info>,
+    
     pub operator: Signer<'info>,
     
+    pub token_program: Option<Program<'info, Token>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UnstakeSol<'info> {
+pub struct CloseDelegation<'info> {
     #[account(
         mut,
-        seeds = [b"node", operator.key().as_ref()],
-        bump,
-        has_one = operator
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
     )]
     pub node_account: Account<'info, NodeAccount>,
     
+    #[account(
+        mut,
+        seeds = [b"delegation", node_account.key().as_ref(), delegator.key().as_ref()],
+        bump,
+        has_one = delegator,
+        close = delegator
+    )]
+    pub delegation: Account<'info, Delegation>,
+    
     #[account(mut)]
-    pub operator: Signer<'info>,
+    pub delegator: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawUnstaked<'info> {
+pub struct DeregisterNode<'info> {
     #[account(
         mut,
-        seeds = [b"node", operator.key().as_ref()],
+        seeds = [b"node", node_account.node_id.as_ref()],
         bump,
-        has_one = operator
+        has_one = operator,
+        close = operator
     )]
     pub node_account: Account<'info, NodeAccount>,
     
+    #[account(
+        mut,
+        seeds = [b"operator", operator.key().as_ref()],
+        bump
+    )]
+    pub operator_profile: Account<'info, OperatorProfile>,
+    
+    #[account(
+        mut,
+        seeds = [b"earnings", node_account.key().as_ref()],
+        bump
+    )]
+    pub earnings_vault: SystemAccount<'info>,
+    
     #[account(
         mut,
         seeds = [b"stake", node_account.key().as_ref()],
@@ -450,26 +3958,20 @@ pub struct WithdrawUnstaked<'info> {
     
     #[account(
         mut,
-        seeds = [b"registry"],
+        seeds = [b"stake_token", node_account.key().as_ref()],
         bump
     )]
-    pub global_registry: Account<'info, GlobalRegistry>,
+    pub stake_token_vault: Option<Account<'info, TokenAccount>>,
     
     #[account(mut)]
-    pub operator: Signer<'info>,
+    pub operator_token_account: Option<Account<'info, TokenAccount>>,
     
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct UpdateHeartbeat<'info> {
     #[account(
         mut,
-        seeds = [b"node", operator.key().as_ref()],
-        bump,
-        has_one = operator
+        seeds = [b"directory", node_account.directory_page.to_le_bytes().as_ref()],
+        bump
     )]
-    pub node_account: Account<'info, NodeAccount>,
+    pub directory_page: AccountLoader<'info, DirectoryPage>,
     
     #[account(
         mut,
@@ -478,66 +3980,63 @@ pub struct UpdateHeartbeat<'info> {
     )]
     pub global_registry: Account<'info, GlobalRegistry>,
     
+    #[account(mut)]
     pub operator: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateReputation<'info> {
+pub struct FundRewardsPool<'info> {
     #[account(
         mut,
-        seeds = [b"node", node_account.operator.as_ref()],
+        seeds = [b"rewards_pool"],
         bump
     )]
-    pub node_account: Account<'info, NodeAccount>,
+    pub rewards_pool: SystemAccount<'info>,
     
-    #[account(
-        seeds = [b"registry"],
-        bump,
-        has_one = authority
-    )]
-    pub global_registry: Account<'info, GlobalRegistry>,
+    #[account(mut)]
+    pub funder: Signer<'info>,
     
-    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SlashNode<'info> {
+pub struct CrankEpoch<'info> {
     #[account(
         mut,
-        seeds = [b"node", node_account.operator.as_ref()],
+        seeds = [b"registry"],
         bump
     )]
-    pub node_account: Account<'info, NodeAccount>,
+    pub global_registry: Account<'info, GlobalRegistry>,
     
     #[account(
-        mut,
-        seeds = [b"stake", node_account.key().as_ref()],
+        init,
+        payer = payer,
+        space = 8 + RewardEpoch::LEN,
+        seeds = [b"epoch", global_registry.current_epoch.to_le_bytes().as_ref()],
         bump
     )]
-    pub stake_vault: SystemAccount<'info>,
+    pub reward_epoch: Account<'info, RewardEpoch>,
     
     #[account(
-        mut,
-        seeds = [b"registry"],
-        bump,
-        has_one = authority,
-        has_one = protocol_fee_vault
+        seeds = [b"rewards_pool"],
+        bump
     )]
-    pub global_registry: Account<'info, GlobalRegistry>,
+    pub rewards_pool: SystemAccount<'info>,
     
-    /// CHECK: Protocol fee vault
     #[account(mut)]
-    pub protocol_fee_vault: AccountInfo<'info>,
+    pub payer: Signer<'info>,
     
-    pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct RecordEarnings<'info> {
+pub struct ClaimEpochRewards<'info> {
     #[account(
         mut,
-        seeds = [b"node", operator.key().as_ref()],
+        seeds = [b"node", node_account.node_id.as_ref()],
         bump,
         has_one = operator
     )]
@@ -545,27 +4044,37 @@ pub struct RecordEarnings<'info> {
     
     #[account(
         mut,
-        seeds = [b"registry"],
+        seeds = [
+            b"node_epoch",
+            node_account.key().as_ref(),
+            node_epoch_points.epoch.to_le_bytes().as_ref()
+        ],
+        bump,
+        constraint = node_epoch_points.node == node_account.key() @ ErrorCode::InvalidEpochPoints,
+        close = operator
+    )]
+    pub node_epoch_points: Account<'info, NodeEpochPoints>,
+    
+    #[account(
+        mut,
+        seeds = [b"epoch", node_epoch_points.epoch.to_le_bytes().as_ref()],
         bump
     )]
-    pub global_registry: Account<'info, GlobalRegistry>,
+    pub reward_epoch: Account<'info, RewardEpoch>,
     
-    pub operator: Signer<'info>,
-}
-
-#[derive(Accounts)]
-pub struct ClaimEarnings<'info> {
     #[account(
         mut,
-        seeds = [b"node", operator.key().as_ref()],
-        bump,
-        has_one = operator
+        seeds = [b"rewards_pool"],
+        bump
     )]
-    pub node_account: Account<'info, NodeAccount>,
+    pub rewards_pool: SystemAccount<'info>,
     
-    /// CHECK: Earnings vault
-    #[account(mut)]
-    pub earnings_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
     
     #[account(mut)]
     pub operator: Signer<'info>,
@@ -574,29 +4083,73 @@ pub struct ClaimEarnings<'info> {
 }
 
 #[derive(Accounts)]
-pub struct DeactivateNode<'info> {
+pub struct SweepRewardEpoch<'info> {
+    #[account(
+        mut,
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
     #[account(
         mut,
-        seeds = [b"node", operator.key().as_ref()],
+        seeds = [b"epoch", reward_epoch.epoch.to_le_bytes().as_ref()],
         bump,
-        has_one = operator
+        close = rewards_pool
     )]
-    pub node_account: Account<'info, NodeAccount>,
+    pub reward_epoch: Account<'info, RewardEpoch>,
     
-    pub operator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"rewards_pool"],
+        bump
+    )]
+    pub rewards_pool: SystemAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ReactivateNode<'info> {
+pub struct ExpireEpochPoints<'info> {
     #[account(
         mut,
-        seeds = [b"node", operator.key().as_ref()],
-        bump,
-        has_one = operator
+        seeds = [b"node", node_account.node_id.as_ref()],
+        bump
     )]
     pub node_account: Account<'info, NodeAccount>,
     
-    pub operator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"node_epoch",
+            node_account.key().as_ref(),
+            node_epoch_points.epoch.to_le_bytes().as_ref()
+        ],
+        bump,
+        constraint = node_epoch_points.node == node_account.key() @ ErrorCode::InvalidEpochPoints,
+        constraint = node_epoch_points.epoch < global_registry.current_epoch @ ErrorCode::EpochNotEnded,
+        close = operator
+    )]
+    pub node_epoch_points: Account<'info, NodeEpochPoints>,
+    
+    /// CHECK: must be the epoch's closed (swept) `RewardEpoch` address.
+    #[account(
+        seeds = [b"epoch", node_epoch_points.epoch.to_le_bytes().as_ref()],
+        bump,
+        constraint = reward_epoch.data_is_empty() @ ErrorCode::ClaimPeriodActive
+    )]
+    pub reward_epoch: UncheckedAccount<'info>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump
+    )]
+    pub global_registry: Account<'info, GlobalRegistry>,
+    
+    /// Receives the points account's rent, having paid it.
+    #[account(
+        mut,
+        address = node_account.operator
+    )]
+    pub operator: SystemAccount<'info>,
 }
 
 #[account]
@@ -608,37 +4161,424 @@ pub struct GlobalRegistry {
     pub protocol_fee_vault: Pubkey,
     pub total_bandwidth_served: u64,
     pub total_earnings_distributed: u64,
+    #[max_len(MAX_EARNINGS_SOURCES)]
+    pub earnings_sources: Vec<Pubkey>,
+    pub stake_mint: Option<Pubkey>,
+    pub config: RegistryConfig,
+    #[max_len(MAX_ARBITERS)]
+    pub arbiters: Vec<Pubkey>,
+    pub directory_pages: u32,
+    pub directory_len: u64,
+    pub pending_authority: Option<Pubkey>,
+    #[max_len(MAX_MULTISIG_SIGNERS)]
+    pub multisig_signers: Vec<Pubkey>,
+    pub multisig_threshold: u8,
+    pub is_paused: bool,
+    pub unpaused_at: i64,
+    pub current_epoch: u64,
+    pub epoch_started_at: i64,
+    pub epoch_total_points: u128,
+    pub rewards_reserved: u64,
+}
+
+impl GlobalRegistry {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 4 + 8 + 32 + 8 + 8 + (4 + MAX_EARNINGS_SOURCES * 32) + (1 + 32)
+        + RegistryConfig::LEN + (4 + MAX_ARBITERS * 32) + 4 + 8
+        + (1 + 32) + (4 + MAX_MULTISIG_SIGNERS * 32) + 1 + 1 + 8
+        + 8 + 8 + 16 + 8;
+
+    /// Directory page that the next registered node is appended to.
+    pub fn next_directory_page(&self) -> u32 {
+        self.directory_len.checked_div(DIRECTORY_PAGE_SIZE as u64).unwrap() as u32
+    }
+}
+
+/// Fixed-size page of node summaries so clients can enumerate the network
+/// without `getProgramAccounts`. Entries are appended in registration order;
+/// deregistered nodes leave a zeroed slot.
+#[account(zero_copy)]
+pub struct DirectoryPage {
+    pub page_index: u32,
+    pub count: u32,
+    pub entries: [DirectoryEntry; DIRECTORY_PAGE_SIZE],
+}
+
+impl DirectoryPage {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 4 + 4 + DIRECTORY_PAGE_SIZE * DirectoryEntry::LEN;
+}
+
+/// Carries everything vrf-selection needs to weigh and filter a candidate,
+/// so selection can read whole pages instead of every `NodeAccount`.
+#[zero_copy]
+#[derive(Default)]
+pub struct DirectoryEntry {
+    pub node: Pubkey,
+    pub operator: Pubkey,
+    pub stake: u64,
+    pub delegated_stake: u64,
+    pub registered_at: i64,
+    pub bandwidth_gbps: u16,
+    pub country_code: [u8; 2],
+    pub reputation: u8,
+    pub is_active: u8,
+    pub uptime_percentage: u8,
+    pub _padding: [u8; 1],
+}
+
+impl DirectoryEntry {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 2 + 2 + 1 + 1 + 1 + 1;
+
+    fn from_node(key: Pubkey, node: &NodeAccount) -> Self {
+        Self {
+            node: key,
+            operator: node.operator,
+            stake: node.stake_amount,
+            delegated_stake: node.delegated_stake,
+            registered_at: node.registered_at,
+            bandwidth_gbps: node.bandwidth_gbps,
+            country_code: node.metadata.country_code,
+            reputation: node.reputation,
+            is_active: node.is_active as u8,
+            uptime_percentage: node.uptime_percentage,
+            _padding: [0; 1],
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct RegistryConfig {
+    pub min_stake: u64,
+    pub unbonding_period: i64,
+    pub min_reputation: u8,
+    pub protocol_fee_bps: u16,
+    pub downtime_slash_bps: u16,
+    pub malicious_slash_bps: u16,
+    pub heartbeat_timeout: i64,
+    pub downtime_bounty_bps: u16,
+    pub downtime_cooldown: i64,
+    pub dispute_window: i64,
+    pub slash_bond: u64,
+    pub arbiter_quorum: u8,
+    pub arbitration_period: i64,
+    pub epoch_duration: i64,
+    pub epoch_emission: u64,
+    pub max_bandwidth_gbps: u16,
+    pub reward_claim_period: i64,
+}
+
+impl RegistryConfig {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 8 + 8 + 1 + 2 + 2 + 2 + 8 + 2 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 2 + 8;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct NodeAccount {
+    pub node_id: Pubkey,
+    pub operator: Pubkey,
+    pub pending_operator: Option<Pubkey>,
+    pub stake_amount: u64,
+    pub reputation: u8,
+    pub metadata: NodeMetadata,
+    pub bandwidth_gbps: u16,
+    pub total_bandwidth_served: u64,
+    pub uptime_percentage: u8,
+    pub last_heartbeat: i64,
+    pub earnings_accumulated: u64,
+    pub is_active: bool,
+    pub registered_at: i64,
+    pub unbonding_amount: u64,
+    pub unbonding_shares: u64,
+    pub next_ticket_id: u64,
+    pub open_tickets: u16,
+    pub slash_count: u16,
+    pub commission_bps: u16,
+    pub delegated_stake: u64,
+    pub delegation_shares: u64,
+    pub delegated_unbonding: u64,
+    pub delegated_unbonding_shares: u64,
+    pub acc_reward_per_share: u128,
+    pub stake_mint: Option<Pubkey>,
+    pub last_downtime_slash: i64,
+    pub uptime_bitmap: u128,
+    pub uptime_epoch: i64,
+    pub last_slashed_at: i64,
+    pub quality_score_total: u64,
+    pub quality_report_count: u32,
+    pub reputation_adjustment: i16,
+    pub adjustment_at: i64,
+    pub pending_slash: bool,
+    pub delegation_count: u32,
+    pub directory_page: u32,
+    pub directory_slot: u32,
+    pub pending_bandwidth_gbps: u16,
+    pub bandwidth_effective_epoch: u64,
+    pub open_epoch_points: u32,
+}
+
+impl NodeAccount {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 32 + (1 + 32) + 8 + 1 + NodeMetadata::LEN + 2 + 8 + 1 + 8 + 8 + 1
+        + 8 + 8 + 8 + 8 + 2 + 2 + 2 + 8 + 8 + 8 + 8 + 16 + (1 + 32) + 8 + 16 + 8
+        + 8 + 8 + 4 + 2 + 8 + 1 + 4 + 4 + 4 + 2 + 8 + 4;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct NodeMetadata {
+    /// ISO-3166-1 alpha-2, uppercase.
+    pub country_code: [u8; 2],
+    #[max_len(MAX_GEOHASH_LEN)]
+    pub geohash: String,
+    #[max_len(MAX_ENDPOINTS)]
+    pub endpoints: Vec<Endpoint>,
+    /// WireGuard/Noise static public key.
+    pub noise_public_key: [u8; 32],
+    /// `CAPABILITY_*` bitflags.
+    pub capabilities: u8,
+}
+
+impl NodeMetadata {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 2 + (4 + MAX_GEOHASH_LEN) + (4 + MAX_ENDPOINTS * Endpoint::LEN) + 32 + 1;
+
+    fn validate(&self) -> Result<()> {
+        require!(
+            self.country_code.iter().all(u8::is_ascii_uppercase),
+            ErrorCode::InvalidCountryCode
+        );
+        require!(
+            !self.geohash.is_empty()
+                && self.geohash.len() <= MAX_GEOHASH_LEN
+                && self.geohash.bytes().all(|c| GEOHASH_ALPHABET.contains(&c)),
+            ErrorCode::InvalidGeohash
+        );
+        require!(
+            !self.endpoints.is_empty() && self.endpoints.len() <= MAX_ENDPOINTS,
+            ErrorCode::InvalidEndpoints
+        );
+        for endpoint in &self.endpoints {
+            endpoint.validate()?;
+        }
+        require!(self.noise_public_key != [0u8; 32], ErrorCode::InvalidNoiseKey);
+        require!(
+            self.capabilities & !KNOWN_CAPABILITIES == 0,
+            ErrorCode::InvalidCapabilities
+        );
+
+        // Advertised transports must be reachable through a listed endpoint.
+        if self.capabilities & CAPABILITY_IPV6 != 0 {
+            require!(
+                self.endpoints.iter().any(|e| e.address_kind == AddressKind::Ipv6),
+                ErrorCode::InvalidCapabilities
+            );
+        }
+        if self.capabilities & CAPABILITY_UDP != 0 {
+            require!(
+                self.endpoints.iter().any(|e| e.protocol == TransportProtocol::Udp),
+                ErrorCode::InvalidCapabilities
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct Endpoint {
+    pub address_kind: AddressKind,
+    /// IPv4 addresses use the first 4 bytes; the rest must be zero.
+    pub address: [u8; 16],
+    pub port: u16,
+    pub protocol: TransportProtocol,
+}
+
+impl Endpoint {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 1 + 16 + 2 + 1;
+
+    fn validate(&self) -> Result<()> {
+        require!(self.port != 0, ErrorCode::InvalidEndpoints);
+        require!(self.address != [0u8; 16], ErrorCode::InvalidEndpoints);
+        if self.address_kind == AddressKind::Ipv4 {
+            require!(self.address[4..] == [0u8; 12], ErrorCode::InvalidEndpoints);
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum AddressKind {
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum TransportProtocol {
+    Udp,
+    Tcp,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct OperatorProfile {
+    pub operator: Pubkey,
+    pub node_count: u32,
+    pub created_at: i64,
+}
+
+impl OperatorProfile {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 4 + 8;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UnbondingTicket {
+    pub node: Pubkey,
+    pub ticket_id: u64,
+    pub amount: u64,
+    /// Claim on the node's unbonding pool, which slashes shrink.
+    pub shares: u64,
+    pub release_at: i64,
+}
+
+impl UnbondingTicket {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RewardEpoch {
+    pub epoch: u64,
+    pub total_points: u128,
+    pub emission: u64,
+    pub claimed: u64,
+    pub finalized_at: i64,
+}
+
+impl RewardEpoch {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 8 + 16 + 8 + 8 + 8;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct NodeEpochPoints {
+    pub node: Pubkey,
+    pub epoch: u64,
+    pub points: u128,
+}
+
+impl NodeEpochPoints {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 8 + 16;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SlashProposal {
+    pub node: Pubkey,
+    pub reporter: Pubkey,
+    pub evidence_hash: [u8; 32],
+    pub reporter_bond: u64,
+    pub counter_bond: u64,
+    pub created_at: i64,
+    pub dispute_deadline: i64,
+    pub arbitration_deadline: i64,
+    pub disputed: bool,
+    pub status: SlashProposalStatus,
+    #[max_len(MAX_ARBITERS)]
+    pub voters: Vec<Pubkey>,
+    pub uphold_votes: u8,
+    pub reject_votes: u8,
+    /// The arbiter set when the proposal was opened.
+    #[max_len(MAX_ARBITERS)]
+    pub arbiters: Vec<Pubkey>,
+}
+
+impl SlashProposal {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + (4 + MAX_ARBITERS * 32) + 1 + 1
+        + (4 + MAX_ARBITERS * 32);
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct QualityReport {
+    pub node: Pubkey,
+    pub reporter: Pubkey,
+    pub score: u8,
+    pub submitted_at: i64,
+    pub last_request: Pubkey,
 }
 
-impl GlobalRegistry {
+impl QualityReport {
     #[allow(clippy::arithmetic_side_effects)]
-    pub const LEN: usize = 32 + 4 + 8 + 32 + 8 + 8;
+    pub const LEN: usize = 32 + 32 + 1 + 8 + 32;
+}
+
+/// Leading fields of vrf-selection's `VrfRequest`, in its Borsh layout.
+#[derive(AnchorDeserialize)]
+struct VrfRequestPrefix {
+    user: Pubkey,
+    _nonce: u64,
+    _seed: [u8; 32],
+    _timestamp: i64,
+    is_fulfilled: bool,
+    _selected_node: Option<Pubkey>,
+    _randomness: [u8; 32],
+    _hop_count: u8,
+    _constraints: [bool; 2],
+    circuit: Vec<Pubkey>,
 }
 
 #[account]
 #[derive(InitSpace)]
-pub struct NodeAccount {
-    pub operator: Pubkey,
-    pub stake_amount: u64,
-    pub reputation: u8,
-    #[max_len(64)]
-    pub location: String,
-    #[max_len(45)]
-    pub ip_address: String,
-    pub bandwidth_gbps: u16,
-    pub total_bandwidth_served: u64,
-    pub uptime_percentage: u8,
-    pub last_heartbeat: i64,
-    pub earnings_accumulated: u64,
-    pub is_active: bool,
-    pub registered_at: i64,
+pub struct Delegation {
+    pub delegator: Pubkey,
+    pub node: Pubkey,
+    pub shares: u64,
+    pub reward_debt: u128,
+    pub pending_rewards: u64,
+    /// Claim on the node's delegated unbonding pool, which slashes shrink.
+    pub unbonding_shares: u64,
     pub unbonding_until: i64,
-    pub slash_count: u16,
 }
 
-impl NodeAccount {
+impl Delegation {
     #[allow(clippy::arithmetic_side_effects)]
-    pub const LEN: usize = 32 + 8 + 1 + (4 + 64) + (4 + 45) + 2 + 8 + 1 + 8 + 8 + 1 + 8 + 8 + 2;
+    pub const LEN: usize = 32 + 32 + 8 + 16 + 8 + 8 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum SlashProposalStatus {
+    Pending,
+    Upheld,
+    Rejected,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReputationTrigger {
+    Heartbeat,
+    Downtime,
+    Slash,
+    QualityReport,
+    Adjustment,
+    Refresh,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ReputationBreakdown {
+    pub score: u8,
+    pub uptime: u8,
+    pub slashing: u8,
+    pub quality: u8,
+    pub bandwidth: u8,
+    pub stake_age: u8,
+    pub adjustment: i16,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
@@ -653,11 +4593,64 @@ pub struct RegistryInitialized {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct EarningsSourceAdded {
+    pub source: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EarningsSourceRemoved {
+    pub source: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub old_config: RegistryConfig,
+    pub new_config: RegistryConfig,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigUpdated {
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeMintUpdated {
+    pub stake_mint: Option<Pubkey>,
+    pub min_stake: u64,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct NodeRegistered {
+    pub node_id: Pubkey,
     pub operator: Pubkey,
-    pub location: String,
-    pub ip_address: String,
+    pub metadata: NodeMetadata,
     pub bandwidth_gbps: u16,
     pub timestamp: i64,
 }
@@ -673,7 +4666,9 @@ pub struct StakeDeposited {
 #[event]
 pub struct UnstakeInitiated {
     pub operator: Pubkey,
+    pub ticket_id: u64,
     pub amount: u64,
+    pub remaining_stake: u64,
     pub unbonding_until: i64,
     pub timestamp: i64,
 }
@@ -681,13 +4676,24 @@ pub struct UnstakeInitiated {
 #[event]
 pub struct StakeWithdrawn {
     pub operator: Pubkey,
+    pub ticket_id: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnbondingCancelled {
+    pub operator: Pubkey,
+    pub ticket_id: u64,
     pub amount: u64,
+    pub total_stake: u64,
     pub timestamp: i64,
 }
 
 #[event]
 pub struct HeartbeatUpdated {
     pub operator: Pubkey,
+    pub uptime_percentage: u8,
     pub timestamp: i64,
     pub bandwidth_served_gb: u64,
 }
@@ -697,6 +4703,9 @@ pub struct ReputationUpdated {
     pub operator: Pubkey,
     pub old_score: u8,
     pub new_score: u8,
+    pub trigger: ReputationTrigger,
+    pub reason_code: u16,
+    pub breakdown: ReputationBreakdown,
     pub timestamp: i64,
 }
 
@@ -705,14 +4714,82 @@ pub struct NodeSlashed {
     pub operator: Pubkey,
     pub violation_type: ViolationType,
     pub slash_amount: u64,
+    pub delegated_slash_amount: u64,
+    pub unbonding_slash_amount: u64,
+    pub delegated_unbonding_slash_amount: u64,
     pub remaining_stake: u64,
     pub timestamp: i64,
 }
 
+#[event]
+pub struct ArbiterAdded {
+    pub arbiter: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ArbiterRemoved {
+    pub arbiter: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SlashProposed {
+    pub operator: Pubkey,
+    pub reporter: Pubkey,
+    pub evidence_hash: [u8; 32],
+    pub dispute_deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SlashDisputed {
+    pub operator: Pubkey,
+    pub counter_bond: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SlashVoteCast {
+    pub operator: Pubkey,
+    pub arbiter: Pubkey,
+    pub uphold: bool,
+    pub uphold_votes: u8,
+    pub reject_votes: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SlashResolved {
+    pub operator: Pubkey,
+    pub arbiter: Pubkey,
+    pub upheld: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SlashExecuted {
+    pub operator: Pubkey,
+    pub upheld: bool,
+    pub slash_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DowntimeReported {
+    pub operator: Pubkey,
+    pub reporter: Pubkey,
+    pub last_heartbeat: i64,
+    pub bounty: u64,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct EarningsRecorded {
     pub operator: Pubkey,
+    pub source: Pubkey,
     pub amount: u64,
+    pub delegator_rewards: u64,
     pub protocol_fee: u64,
     pub timestamp: i64,
 }
@@ -736,15 +4813,135 @@ pub struct NodeReactivated {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct RewardsPoolFunded {
+    pub funder: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EpochFinalized {
+    pub epoch: u64,
+    pub total_points: u128,
+    pub emission: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EpochRewardsClaimed {
+    pub operator: Pubkey,
+    pub epoch: u64,
+    pub points: u128,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardEpochSwept {
+    pub epoch: u64,
+    pub unclaimed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EpochPointsExpired {
+    pub node_id: Pubkey,
+    pub epoch: u64,
+    pub points: u128,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NodeDeregistered {
+    pub node_id: Pubkey,
+    pub operator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NodeMetadataUpdated {
+    pub node_id: Pubkey,
+    pub operator: Pubkey,
+    pub metadata: NodeMetadata,
+    pub bandwidth_gbps: u16,
+    pub effective_epoch: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OperatorTransferInitiated {
+    pub node_id: Pubkey,
+    pub operator: Pubkey,
+    pub pending_operator: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OperatorTransferred {
+    pub node_id: Pubkey,
+    pub previous_operator: Pubkey,
+    pub new_operator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CommissionUpdated {
+    pub operator: Pubkey,
+    pub old_commission_bps: u16,
+    pub new_commission_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeDelegated {
+    pub delegator: Pubkey,
+    pub operator: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DelegationEvicted {
+    pub delegator: Pubkey,
+    pub operator: Pubkey,
+    pub amount: u64,
+    pub rewards: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeUndelegated {
+    pub delegator: Pubkey,
+    pub operator: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub unbonding_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DelegationWithdrawn {
+    pub delegator: Pubkey,
+    pub operator: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DelegatorRewardsClaimed {
+    pub delegator: Pubkey,
+    pub operator: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
 #[error_code]
 pub enum ErrorCode {
-    #[msg("Location string exceeds maximum length of 64 characters")]
-    LocationTooLong,
-    #[msg("IP address string exceeds maximum length of 45 characters")]
-    IpAddressTooLong,
-    #[msg("Bandwidth must be greater than 0")]
+    #[msg("Bandwidth must be greater than 0 and within the registry maximum")]
     InvalidBandwidth,
-    #[msg("Insufficient stake amount. Minimum 100 SOL required")]
+    #[msg("Insufficient stake amount. Below the registry minimum")]
     InsufficientStake,
     #[msg("Insufficient balance for operation")]
     InsufficientBalance,
@@ -758,8 +4955,397 @@ pub enum ErrorCode {
     NodeAlreadyActive,
     #[msg("Invalid reputation score. Must be 0-100")]
     InvalidReputation,
-    #[msg("Node reputation too low. Minimum score: 50")]
+    #[msg("Node reputation below the registry minimum")]
     LowReputation,
     #[msg("No earnings available to claim")]
     NoEarningsToClaim,
+    #[msg("Signer is not a registered earnings source")]
+    UnauthorizedEarningsSource,
+    #[msg("Earnings source is already registered")]
+    EarningsSourceAlreadyRegistered,
+    #[msg("Earnings source is not registered")]
+    EarningsSourceNotRegistered,
+    #[msg("Earnings source list is full")]
+    TooManyEarningsSources,
+    #[msg("Amount must be greater than 0")]
+    InvalidAmount,
+    #[msg("Too many unbonding tickets open. Withdraw or cancel one first")]
+    TooManyUnbondingTickets,
+    #[msg("Commission must be at most 10000 basis points")]
+    InvalidCommission,
+    #[msg("Delegated stake has been fully slashed")]
+    DelegationPoolDepleted,
+    #[msg("Instruction does not match the node's stake mode")]
+    WrongStakeMode,
+    #[msg("Stake mint does not match the registry stake mint")]
+    InvalidStakeMint,
+    #[msg("Stake mint cannot change while nodes are registered")]
+    NodesRegistered,
+    #[msg("Token accounts are required for SPL stake")]
+    MissingStakeTokenAccounts,
+    #[msg("Token account is not owned by the protocol fee vault")]
+    InvalidProtocolFeeTokenAccount,
+    #[msg("Token account is not owned by the delegator")]
+    InvalidDelegatorTokenAccount,
+    #[msg("Registry config value out of bounds")]
+    InvalidConfig,
+    #[msg("Node heartbeat is within the allowed timeout")]
+    HeartbeatNotStale,
+    #[msg("Node was slashed for downtime too recently")]
+    DowntimeCooldownActive,
+    #[msg("Node has not sent a heartbeat since its last downtime slash")]
+    NodeNotRecovered,
+    #[msg("Reputation adjustment must be non-zero and within the per-call bound")]
+    InvalidReputationAdjustment,
+    #[msg("Quality report was updated too recently")]
+    QualityReportCooldownActive,
+    #[msg("Reporter was not routed through this node by a fulfilled VRF request")]
+    UnprovenReporter,
+    #[msg("VRF request was already used for this node's quality report")]
+    VrfRequestAlreadyUsed,
+    #[msg("Malicious slashing must go through a slash proposal")]
+    MaliciousSlashRequiresProposal,
+    #[msg("Node has a pending slash proposal")]
+    SlashProposalPending,
+    #[msg("Slash proposal has already been disputed")]
+    SlashAlreadyDisputed,
+    #[msg("Slash proposal has not been disputed")]
+    SlashNotDisputed,
+    #[msg("Slash proposal has already been resolved")]
+    SlashAlreadyResolved,
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+    #[msg("Disputed slash is awaiting an arbiter decision")]
+    AwaitingArbitration,
+    #[msg("Signer is not a registered arbiter")]
+    UnauthorizedArbiter,
+    #[msg("Arbitration period for this proposal has ended")]
+    ArbitrationClosed,
+    #[msg("Arbiter cannot vote on its own proposal")]
+    ConflictedArbiter,
+    #[msg("Arbiter has already voted on this proposal")]
+    ArbiterAlreadyVoted,
+    #[msg("Arbiter is already registered")]
+    ArbiterAlreadyRegistered,
+    #[msg("Arbiter is not registered")]
+    ArbiterNotRegistered,
+    #[msg("Arbiter set is full")]
+    TooManyArbiters,
+    #[msg("New operator must differ from the current operator")]
+    InvalidNewOperator,
+    #[msg("Signer is not the pending operator for this node")]
+    NoPendingOperatorTransfer,
+    #[msg("Country code must be an uppercase ISO-3166 alpha-2 code")]
+    InvalidCountryCode,
+    #[msg("Geohash must be 1-12 base32 geohash characters")]
+    InvalidGeohash,
+    #[msg("Node must list 1-4 endpoints with a non-zero address and port")]
+    InvalidEndpoints,
+    #[msg("Noise public key must be set")]
+    InvalidNoiseKey,
+    #[msg("Capability flags are unknown or not backed by an endpoint")]
+    InvalidCapabilities,
+    #[msg("Node still has stake or unbonding tickets")]
+    StakeOutstanding,
+    #[msg("Node still has open delegations")]
+    DelegationsOutstanding,
+    #[msg("Node has unclaimed earnings")]
+    EarningsOutstanding,
+    #[msg("Delegation still holds shares, unbonding stake or rewards")]
+    DelegationNotEmpty,
+    #[msg("Directory page for the next registration has not been created")]
+    DirectoryPageMissing,
+    #[msg("Directory entry does not belong to this node")]
+    DirectoryEntryMismatch,
+    #[msg("Signer is not the pending registry authority")]
+    NoPendingAuthorityTransfer,
+    #[msg("Multisig signers must be unique, at most 5, with a threshold between 1 and the signer count")]
+    InvalidMultisig,
+    #[msg("Not enough multisig approvals")]
+    InsufficientApprovals,
+    #[msg("Registry is paused")]
+    RegistryPaused,
+    #[msg("Current reward epoch has not ended")]
+    EpochNotEnded,
+    #[msg("Epoch points account does not belong to this node")]
+    InvalidEpochPoints,
+    #[msg("Node has unclaimed epoch reward points")]
+    EpochPointsOutstanding,
+    #[msg("Reward epoch claim period has not ended")]
+    ClaimPeriodActive,
+    #[msg("Directory page is not needed until the current one is full")]
+    DirectoryPageNotNeeded,
+    #[msg("Unbonding stake was fully slashed; withdraw open tickets first")]
+    UnbondingPoolDepleted,
+    #[msg("Node has no bonded or unbonding stake to slash")]
+    NoSlashableStake,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH: i64 = UPTIME_EPOCH_SECONDS;
+    const PERIOD: i64 = REPUTATION_DECAY_PERIOD;
+
+    /// A node with every field zeroed, which is a valid Borsh encoding.
+    fn node() -> NodeAccount {
+        NodeAccount::deserialize(&mut &[0u8; NodeAccount::LEN][..]).unwrap()
+    }
+
+    fn endpoint(address_kind: AddressKind, protocol: TransportProtocol) -> Endpoint {
+        let mut address = [0u8; 16];
+        address[..4].copy_from_slice(&[203, 0, 113, 7]);
+        if address_kind == AddressKind::Ipv6 {
+            address[4..].copy_from_slice(&[1; 12]);
+        }
+        Endpoint {
+            address_kind,
+            address,
+            port: 51820,
+            protocol,
+        }
+    }
+
+    fn metadata() -> NodeMetadata {
+        NodeMetadata {
+            country_code: *b"DE",
+            geohash: "u33dc0".to_string(),
+            endpoints: vec![endpoint(AddressKind::Ipv4, TransportProtocol::Udp)],
+            noise_public_key: [7; 32],
+            capabilities: CAPABILITY_RELAY | CAPABILITY_UDP,
+        }
+    }
+
+    #[test]
+    fn bandwidth_change_waits_for_next_epoch() {
+        let mut node = node();
+        node.bandwidth_gbps = 10;
+        node.pending_bandwidth_gbps = 40;
+        node.bandwidth_effective_epoch = 3;
+
+        apply_pending_bandwidth(&mut node, 2);
+        assert_eq!((node.bandwidth_gbps, node.pending_bandwidth_gbps), (10, 40));
+
+        apply_pending_bandwidth(&mut node, 3);
+        assert_eq!((node.bandwidth_gbps, node.pending_bandwidth_gbps), (40, 0));
+    }
+
+    #[test]
+    fn slash_shrinks_unbonding_tickets_pro_rata() {
+        let mut node = node();
+        node.stake_amount = 1_000;
+        for amount in [600, 200] {
+            let shares =
+                issue_unbonding_shares(amount, node.unbonding_amount, node.unbonding_shares).unwrap();
+            node.unbonding_amount += amount;
+            node.unbonding_shares += shares;
+        }
+
+        let slashed = apply_slash(&mut node, 5000);
+        assert_eq!((slashed.stake, slashed.unbonding, slashed.total()), (500, 400, 900));
+        assert_eq!(node.unbonding_amount, 400);
+
+        // A later ticket buys in at the slashed price and keeps its value.
+        let shares = issue_unbonding_shares(100, node.unbonding_amount, node.unbonding_shares).unwrap();
+        node.unbonding_amount += 100;
+        node.unbonding_shares += shares;
+        assert_eq!(redeem_unbonding_shares(600, node.unbonding_amount, node.unbonding_shares), 300);
+        assert_eq!(redeem_unbonding_shares(shares, node.unbonding_amount, node.unbonding_shares), 100);
+
+        apply_slash(&mut node, 10000);
+        assert!(issue_unbonding_shares(100, node.unbonding_amount, node.unbonding_shares).is_err());
+    }
+
+    #[test]
+    fn slash_reaches_delegator_unbonding() {
+        let mut node = node();
+        node.delegated_stake = 400;
+        node.delegation_shares = 400;
+        let mut delegation = Delegation::deserialize(&mut &[0u8; Delegation::LEN][..]).unwrap();
+        delegation.shares = 400;
+
+        assert_eq!(unbond_delegation(&mut node, &mut delegation, 400, 0).unwrap(), 400);
+        assert_eq!((node.delegated_stake, node.delegated_unbonding), (0, 400));
+
+        let slashed = apply_slash(&mut node, 2500);
+        assert_eq!((slashed.delegated_unbonding, slashed.total()), (100, 100));
+        assert_eq!(
+            redeem_unbonding_shares(
+                delegation.unbonding_shares,
+                node.delegated_unbonding,
+                node.delegated_unbonding_shares
+            ),
+            300
+        );
+    }
+
+    #[test]
+    fn uptime_counts_heartbeat_epochs_since_registration() {
+        let mut node = node();
+        node.registered_at = 10 * EPOCH;
+        node.uptime_epoch = 10;
+
+        record_uptime(&mut node, 10 * EPOCH, true);
+        assert_eq!((node.uptime_bitmap, node.uptime_percentage), (0b1, 100));
+
+        record_uptime(&mut node, 11 * EPOCH, false);
+        assert_eq!((node.uptime_bitmap, node.uptime_percentage), (0b10, 50));
+
+        // Two epochs pass; the one in between was missed.
+        record_uptime(&mut node, 13 * EPOCH + 5, true);
+        assert_eq!((node.uptime_bitmap, node.uptime_percentage), (0b1001, 50));
+        assert_eq!(node.uptime_epoch, 13);
+    }
+
+    #[test]
+    fn uptime_window_is_capped_and_rolls_over() {
+        let mut node = node();
+        for epoch in 0..200 {
+            record_uptime(&mut node, epoch * EPOCH, true);
+        }
+        assert_eq!(node.uptime_bitmap, u128::MAX);
+        assert_eq!(node.uptime_percentage, 100);
+
+        // Ten silent epochs drop ten of the 128 tracked slots.
+        record_uptime(&mut node, 209 * EPOCH, false);
+        assert_eq!(node.uptime_bitmap.count_ones(), 118);
+        assert_eq!(node.uptime_percentage, 92);
+
+        // A gap longer than the window clears all history.
+        record_uptime(&mut node, (209 + UPTIME_WINDOW_EPOCHS) * EPOCH, true);
+        assert_eq!(node.uptime_bitmap, 1);
+        assert_eq!(node.uptime_percentage, 0);
+    }
+
+    #[test]
+    fn uptime_ignores_out_of_order_timestamps() {
+        let mut node = node();
+        node.uptime_epoch = 5;
+        node.uptime_bitmap = 0b1;
+
+        record_uptime(&mut node, 3 * EPOCH, true);
+        assert_eq!(node.uptime_epoch, 5);
+        assert_eq!(node.uptime_bitmap, 0b1);
+    }
+
+    #[test]
+    fn decay_halvings_counts_whole_periods() {
+        assert_eq!(decay_halvings(0, 100 * PERIOD), 0);
+        assert_eq!(decay_halvings(PERIOD, 2 * PERIOD - 1), 0);
+        assert_eq!(decay_halvings(PERIOD, 2 * PERIOD), 1);
+        assert_eq!(decay_halvings(PERIOD, 4 * PERIOD + 1), 3);
+        assert_eq!(decay_halvings(PERIOD, 100 * PERIOD), 15);
+        assert_eq!(decay_halvings(2 * PERIOD, PERIOD), 0);
+    }
+
+    #[test]
+    fn adjustment_halves_each_period() {
+        let mut node = node();
+        node.adjustment_at = PERIOD;
+
+        for (adjustment, elapsed, expected) in [
+            (20i16, 0, 20i16),
+            (20, 1, 10),
+            (20, 2, 5),
+            (-20, 1, -10),
+            (-20, 3, -2),
+            (20, 99, 0),
+        ] {
+            node.reputation_adjustment = adjustment;
+            assert_eq!(decayed_adjustment(&node, PERIOD + elapsed * PERIOD), expected);
+        }
+    }
+
+    #[test]
+    fn adjustment_is_clamped_after_decay() {
+        let mut node = node();
+        node.adjustment_at = PERIOD;
+
+        node.reputation_adjustment = 15;
+        assert_eq!(next_adjustment(&node, PERIOD, 10), MAX_REPUTATION_ADJUSTMENT);
+        node.reputation_adjustment = -15;
+        assert_eq!(next_adjustment(&node, PERIOD, -10), -MAX_REPUTATION_ADJUSTMENT);
+
+        // Decay applies before the new delta.
+        node.reputation_adjustment = 20;
+        assert_eq!(next_adjustment(&node, 2 * PERIOD, 5), 15);
+    }
+
+    #[test]
+    fn reputation_score_is_clamped() {
+        let mut node = node();
+        node.slash_count = 4;
+        node.last_slashed_at = PERIOD;
+        node.quality_report_count = 1;
+        node.reputation_adjustment = -MAX_REPUTATION_ADJUSTMENT;
+        node.adjustment_at = PERIOD;
+        assert_eq!(compute_reputation(&node, PERIOD).score, 0);
+
+        let mut node = self::node();
+        node.uptime_percentage = 100;
+        node.total_bandwidth_served = FULL_BANDWIDTH_SCORE_GB;
+        node.reputation_adjustment = MAX_REPUTATION_ADJUSTMENT;
+        node.adjustment_at = FULL_STAKE_AGE_SCORE;
+        assert_eq!(compute_reputation(&node, FULL_STAKE_AGE_SCORE).score, 100);
+    }
+
+    #[test]
+    fn fresh_node_scores_neutral_inputs_only() {
+        // No slashes and no reports count as full marks; everything else is 0.
+        let breakdown = compute_reputation(&node(), 0);
+        assert_eq!(breakdown.slashing, 100);
+        assert_eq!(breakdown.quality, 100);
+        assert_eq!(breakdown.score, 40);
+    }
+
+    #[test]
+    fn valid_metadata_is_accepted() {
+        assert!(metadata().validate().is_ok());
+
+        let mut ipv6 = metadata();
+        ipv6.endpoints.push(endpoint(AddressKind::Ipv6, TransportProtocol::Tcp));
+        ipv6.capabilities |= CAPABILITY_IPV6;
+        assert!(ipv6.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_metadata_is_rejected() {
+        let cases: [fn(&mut NodeMetadata); 10] = [
+            |m| m.country_code = *b"de",
+            |m| m.geohash = String::new(),
+            |m| m.geohash = "u33a".to_string(),
+            |m| m.geohash = "u".repeat(MAX_GEOHASH_LEN + 1),
+            |m| m.endpoints.clear(),
+            |m| m.endpoints = vec![m.endpoints[0].clone(); MAX_ENDPOINTS + 1],
+            |m| m.noise_public_key = [0; 32],
+            |m| m.capabilities |= 1 << 7,
+            |m| m.capabilities |= CAPABILITY_IPV6,
+            |m| m.endpoints[0].protocol = TransportProtocol::Tcp,
+        ];
+
+        for (index, mutate) in cases.iter().enumerate() {
+            let mut metadata = metadata();
+            mutate(&mut metadata);
+            assert!(metadata.validate().is_err(), "case {index} was accepted");
+        }
+    }
+
+    #[test]
+    fn invalid_endpoints_are_rejected() {
+        let mut zero_port = endpoint(AddressKind::Ipv4, TransportProtocol::Udp);
+        zero_port.port = 0;
+        assert!(zero_port.validate().is_err());
+
+        let mut unspecified = endpoint(AddressKind::Ipv6, TransportProtocol::Udp);
+        unspecified.address = [0; 16];
+        assert!(unspecified.validate().is_err());
+
+        // IPv4 addresses may only use the first four bytes.
+        let mut padded = endpoint(AddressKind::Ipv4, TransportProtocol::Udp);
+        padded.address[4] = 1;
+        assert!(padded.validate().is_err());
+    }
 }
@@ -1,179 +1,1887 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
 use anchor_lang::solana_program::sysvar;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+use node_registry::{DirectoryEntry, DirectoryPage, GlobalRegistry};
+use sha2::{Digest, Sha512};
+use solana_curve25519::edwards::{
+    multiply_edwards, subtract_edwards, validate_edwards, PodEdwardsPoint,
+};
+use solana_curve25519::scalar::PodScalar;
 
 declare_id!("4SD36sZLcudbMwUqpd9Efp2iBrN5ihMWj8d59aFAoQFT");
 
+const MAX_ORACLES: usize = 16;
+const MAX_STAKE_MULTIPLIER: u64 = 10;
+const MAX_HOPS: usize = 3;
+const MAX_RECENT_REQUESTS: usize = 8;
+const DEFAULT_REQUEST_TIMEOUT_SLOTS: u64 = 1_500;
+const MAX_CALLBACK_ACCOUNTS: usize = 8;
+const REVEAL_DELAY_SLOTS: u64 = 2;
+const SLOT_HASH_ENTRY_LEN: usize = 8 + 32;
+const MAX_FILTER_COUNTRIES: usize = 8;
+const MAX_EXCLUDED_OPERATORS: usize = 4;
+
+// ECVRF-EDWARDS25519-SHA512-TAI (RFC 9381, section 5.5)
+const ECVRF_SUITE_STRING: u8 = 0x03;
+const ECVRF_PROOF_LEN: usize = 80;
+const ECVRF_CHALLENGE_LEN: usize = 16;
+const ED25519_BASEPOINT: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+const ED25519_IDENTITY: [u8; 32] = [
+    0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+const ED25519_COFACTOR: [u8; 32] = [
+    0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
 #[program]
 pub mod vrf_node_selection {
     use super::*;
 
+    pub fn initialize_vrf_config(ctx: Context<InitializeVrfConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.vrf_config;
+        config.admin = ctx.accounts.admin.key();
+        config.stake_weighted = false;
+        config.request_timeout_slots = DEFAULT_REQUEST_TIMEOUT_SLOTS;
+        config.oracles = Vec::new();
+
+        emit!(VrfConfigInitialized {
+            admin: config.admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_oracle(ctx: Context<ManageOracles>, oracle: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.vrf_config;
+        require!(config.oracles.len() < MAX_ORACLES, ErrorCode::OracleRegistryFull);
+        require!(config.find_oracle(&oracle).is_none(), ErrorCode::OracleAlreadyRegistered);
+
+        config.oracles.push(OracleInfo {
+            oracle,
+            fulfillment_count: 0,
+            total_latency: 0,
+            max_latency: 0,
+            last_fulfilled_at: 0,
+        });
+
+        emit!(OracleAdded {
+            oracle,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_oracle(ctx: Context<ManageOracles>, oracle: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.vrf_config;
+        let index = config.find_oracle(&oracle).ok_or(ErrorCode::OracleNotRegistered)?;
+        let removed = config.oracles.remove(index);
+
+        emit!(OracleRemoved {
+            oracle,
+            fulfillment_count: removed.fulfillment_count,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn rotate_oracle(
+        ctx: Context<ManageOracles>,
+        old_oracle: Pubkey,
+        new_oracle: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.vrf_config;
+        require!(config.find_oracle(&new_oracle).is_none(), ErrorCode::OracleAlreadyRegistered);
+        let index = config.find_oracle(&old_oracle).ok_or(ErrorCode::OracleNotRegistered)?;
+
+        // Stats stay with the slot so the operator's track record survives key rotation.
+        config.oracles[index].oracle = new_oracle;
+
+        emit!(OracleRotated {
+            old_oracle,
+            new_oracle,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_stake_weighting(ctx: Context<ManageOracles>, enabled: bool) -> Result<()> {
+        let config = &mut ctx.accounts.vrf_config;
+        config.stake_weighted = enabled;
+
+        emit!(StakeWeightingUpdated {
+            enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_request_timeout(ctx: Context<ManageOracles>, timeout_slots: u64) -> Result<()> {
+        require!(timeout_slots > 0, ErrorCode::InvalidTimeout);
+
+        let config = &mut ctx.accounts.vrf_config;
+        let old_timeout_slots = config.request_timeout_slots;
+        config.request_timeout_slots = timeout_slots;
+
+        emit!(RequestTimeoutUpdated {
+            old_timeout_slots,
+            new_timeout_slots: timeout_slots,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
     pub fn request_random_node(
         ctx: Context<RequestRandomNode>,
+        nonce: u64,
         user_seed: [u8; 32],
+        params: RequestParams,
     ) -> Result<()> {
+        let RequestParams {
+            hop_count,
+            constraints,
+            fee,
+            callback,
+            commitment,
+            filter,
+        } = params;
+        require!(
+            hop_count >= 1 && hop_count as usize <= MAX_HOPS,
+            ErrorCode::InvalidHopCount
+        );
+        filter.validate()?;
+        if let Some(callback) = &callback {
+            callback.validate(&ctx.accounts.vrf_request.key())?;
+        }
+        // Commit-reveal requests have no oracle to pay.
+        require!(commitment.is_none() || fee == 0, ErrorCode::InvalidFee);
+
+        let user_state = &mut ctx.accounts.user_state;
+        require!(nonce == user_state.request_count, ErrorCode::InvalidNonce);
+
+        user_state.user = ctx.accounts.user.key();
+        user_state.request_count = user_state.request_count.checked_add(1).unwrap();
+        if user_state.recent_requests.len() == MAX_RECENT_REQUESTS {
+            user_state.recent_requests.remove(0);
+        }
+        user_state.recent_requests.push(nonce);
+
         let request = &mut ctx.accounts.vrf_request;
         let clock = Clock::get()?;
         
         request.user = ctx.accounts.user.key();
-        request.seed = user_seed;
+        request.nonce = nonce;
+        // Bind the VRF input to this request so a reused user seed still
+        // yields fresh randomness.
+        request.seed = hashv(&[&user_seed, request.user.as_ref(), &nonce.to_le_bytes()])
+            .to_bytes();
         request.timestamp = clock.unix_timestamp;
         request.is_fulfilled = false;
         request.selected_node = None;
+        request.randomness = [0u8; 32];
+        request.hop_count = hop_count;
+        request.constraints = constraints;
+        request.circuit = Vec::new();
+        request.expires_at_slot = clock.slot
+            .checked_add(ctx.accounts.vrf_config.request_timeout_slots)
+            .unwrap();
+        request.fee = fee;
+        request.fee_mint = None;
+        request.callback = callback;
+        request.commitment = commitment;
+        request.request_slot = clock.slot;
+        request.filter = filter;
+        request.callback_pending = false;
+
+        match (
+            &ctx.accounts.fee_mint,
+            &ctx.accounts.fee_escrow,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+        ) {
+            (Some(fee_mint), Some(fee_escrow), Some(user_token_account), Some(token_program)) => {
+                require!(fee > 0, ErrorCode::InvalidFee);
+
+                let transfer_ctx = CpiContext::new(
+                    token_program.to_account_info(),
+                    Transfer {
+                        from: user_token_account.to_account_info(),
+                        to: fee_escrow.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                );
+                token::transfer(transfer_ctx, fee)?;
+
+                request.fee_mint = Some(fee_mint.key());
+            }
+            (None, None, _, _) => {
+                // SOL fees are escrowed in the request account itself.
+                if fee > 0 {
+                    let transfer_ctx = CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.user.to_account_info(),
+                            to: request.to_account_info(),
+                        },
+                    );
+                    anchor_lang::system_program::transfer(transfer_ctx, fee)?;
+                }
+            }
+            _ => return err!(ErrorCode::MissingFeeAccounts),
+        }
 
         emit!(VrfRequested {
             user: request.user,
+            nonce,
+            hop_count,
+            fee,
+            fee_mint: request.fee_mint,
+            expires_at_slot: request.expires_at_slot,
             timestamp: clock.unix_timestamp,
         });
 
         Ok(())
     }
 
-    pub fn fulfill_random_selection(
-        ctx: Context<FulfillRandomSelection>,
-        vrf_result: [u8; 32],
-        node_pool: Vec<Pubkey>,
-        reputation_weights: Vec<u8>,
+    pub fn fulfill_random_selection<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FulfillRandomSelection<'info>>,
+        proof: [u8; ECVRF_PROOF_LEN],
     ) -> Result<()> {
-        require!(node_pool.len() == reputation_weights.len(), ErrorCode::MismatchedLengths);
-        require!(!node_pool.is_empty(), ErrorCode::EmptyNodePool);
-
+        let clock = Clock::get()?;
         let request = &mut ctx.accounts.vrf_request;
         require!(!request.is_fulfilled, ErrorCode::AlreadyFulfilled);
+        require!(clock.slot <= request.expires_at_slot, ErrorCode::RequestExpired);
+        require!(request.commitment.is_none(), ErrorCode::WrongFulfillmentMode);
+
+        let (directory_pages, callback_accounts) =
+            split_remaining_accounts(
+                request,
+                ctx.remaining_accounts,
+                ctx.accounts.node_registry.directory_pages,
+            )?;
+
+        let config = &mut ctx.accounts.vrf_config;
+        let oracle_index = config
+            .find_oracle(&ctx.accounts.vrf_authority.key())
+            .ok_or(ErrorCode::UnauthorizedOracle)?;
+
+        // The oracle's ed25519 signing key doubles as its ECVRF public key.
+        let vrf_output = ecvrf_verify(
+            &ctx.accounts.vrf_authority.key().to_bytes(),
+            &proof,
+            &request.seed,
+        )?;
+        let mut vrf_result = [0u8; 32];
+        vrf_result.copy_from_slice(&vrf_output[..32]);
 
-        let random_value = u64::from_le_bytes([
-            vrf_result[0],
-            vrf_result[1],
-            vrf_result[2],
-            vrf_result[3],
-            vrf_result[4],
-            vrf_result[5],
-            vrf_result[6],
-            vrf_result[7],
-        ]);
-
-        let selected_node = select_weighted_node(
-            random_value,
-            &node_pool,
-            &reputation_weights,
+        let circuit = apply_selection(
+            request,
+            vrf_result,
+            directory_pages,
+            config.stake_weighted,
+            &ctx.accounts.node_registry,
         )?;
+        let selected_node = circuit[0];
 
-        request.selected_node = Some(selected_node);
-        request.is_fulfilled = true;
+        let latency = clock.unix_timestamp.checked_sub(request.timestamp).unwrap().max(0);
+        let oracle_info = &mut config.oracles[oracle_index];
+        oracle_info.fulfillment_count = oracle_info.fulfillment_count.checked_add(1).unwrap();
+        oracle_info.total_latency = oracle_info.total_latency.checked_add(latency).unwrap();
+        oracle_info.max_latency = oracle_info.max_latency.max(latency);
+        oracle_info.last_fulfilled_at = clock.unix_timestamp;
+
+        let fee = request.fee;
+        if fee > 0 {
+            match request.fee_mint {
+                None => {
+                    let request_info = request.to_account_info();
+                    let oracle_info = ctx.accounts.vrf_authority.to_account_info();
+                    **request_info.try_borrow_mut_lamports()? =
+                        request_info.lamports().checked_sub(fee).unwrap();
+                    **oracle_info.try_borrow_mut_lamports()? =
+                        oracle_info.lamports().checked_add(fee).unwrap();
+                }
+                Some(_) => {
+                    let (Some(fee_escrow), Some(oracle_token_account), Some(token_program)) = (
+                        &ctx.accounts.fee_escrow,
+                        &ctx.accounts.oracle_token_account,
+                        &ctx.accounts.token_program,
+                    ) else {
+                        return err!(ErrorCode::MissingFeeAccounts);
+                    };
+                    transfer_from_escrow(
+                        token_program,
+                        fee_escrow,
+                        oracle_token_account.to_account_info(),
+                        &request.key(),
+                        ctx.bumps.fee_escrow.unwrap(),
+                        fee,
+                    )?;
+                }
+            }
+        }
+
+        let user = request.user;
+        let nonce = request.nonce;
+        let callback = defer_callback_if_absent(request, callback_accounts);
+
+        if let Some(callback) = callback {
+            // Persist the fulfilled request so the consumer sees it during the CPI.
+            ctx.accounts.vrf_request.exit(&crate::ID)?;
+
+            invoke_callback(
+                &callback,
+                callback_accounts,
+                &VrfCallbackData {
+                    user,
+                    nonce,
+                    circuit: circuit.clone(),
+                    randomness: vrf_result,
+                },
+                ctx.bumps.vrf_request,
+            )?;
+        }
 
         emit!(NodeSelected {
-            user: request.user,
+            user,
+            nonce,
             selected_node,
-            timestamp: Clock::get()?.unix_timestamp,
+            circuit,
+            vrf_authority: ctx.accounts.vrf_authority.key(),
+            randomness: vrf_result,
+            latency,
+            fee_paid: fee,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Oracle-free fallback: mixes the committed secret with the hash of the
+    /// first slot at least `REVEAL_DELAY_SLOTS` after the request. The user can
+    /// still withhold an unfavourable reveal, so this is weaker than ECVRF.
+    pub fn reveal_selection<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RevealSelection<'info>>,
+        secret: [u8; 32],
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let request = &mut ctx.accounts.vrf_request;
+        require!(!request.is_fulfilled, ErrorCode::AlreadyFulfilled);
+        require!(clock.slot <= request.expires_at_slot, ErrorCode::RequestExpired);
+
+        let commitment = request.commitment.ok_or(ErrorCode::WrongFulfillmentMode)?;
+        require!(
+            hashv(&[&secret]).to_bytes() == commitment,
+            ErrorCode::CommitmentMismatch
+        );
+
+        let target_slot = request.request_slot.checked_add(REVEAL_DELAY_SLOTS).unwrap();
+        require!(clock.slot > target_slot, ErrorCode::RevealTooEarly);
+        let (slot, slot_hash) =
+            find_slot_hash(&ctx.accounts.slot_hashes.try_borrow_data()?, target_slot)?;
+
+        let randomness = hashv(&[&secret, &slot_hash, &request.seed]).to_bytes();
+
+        let (directory_pages, callback_accounts) =
+            split_remaining_accounts(
+                request,
+                ctx.remaining_accounts,
+                ctx.accounts.node_registry.directory_pages,
+            )?;
+        let circuit = apply_selection(
+            request,
+            randomness,
+            directory_pages,
+            ctx.accounts.vrf_config.stake_weighted,
+            &ctx.accounts.node_registry,
+        )?;
+
+        let user = request.user;
+        let nonce = request.nonce;
+        let callback = defer_callback_if_absent(request, callback_accounts);
+
+        if let Some(callback) = callback {
+            ctx.accounts.vrf_request.exit(&crate::ID)?;
+
+            invoke_callback(
+                &callback,
+                callback_accounts,
+                &VrfCallbackData {
+                    user,
+                    nonce,
+                    circuit: circuit.clone(),
+                    randomness,
+                },
+                ctx.bumps.vrf_request,
+            )?;
+        }
+
+        emit!(NodeRevealed {
+            user,
+            nonce,
+            selected_node: circuit[0],
+            circuit,
+            slot,
+            randomness,
+            timestamp: clock.unix_timestamp,
         });
 
         Ok(())
     }
 
+    /// Delivers a callback that was left off at fulfilment. Anyone may call
+    /// it, and a failure only reverts this instruction, never the selection.
+    pub fn retry_callback(ctx: Context<RetryCallback>) -> Result<()> {
+        let request = &mut ctx.accounts.vrf_request;
+        require!(request.callback_pending, ErrorCode::CallbackNotPending);
+        let callback = request.callback.clone().ok_or(ErrorCode::CallbackNotPending)?;
+
+        request.callback_pending = false;
+        let data = VrfCallbackData {
+            user: request.user,
+            nonce: request.nonce,
+            circuit: request.circuit.clone(),
+            randomness: request.randomness,
+        };
+        ctx.accounts.vrf_request.exit(&crate::ID)?;
+
+        invoke_callback(
+            &callback,
+            ctx.remaining_accounts,
+            &data,
+            ctx.bumps.vrf_request,
+        )
+    }
+
     pub fn get_selected_node(_ctx: Context<GetSelectedNode>) -> Result<Option<Pubkey>> {
         let request = &_ctx.accounts.vrf_request;
         Ok(request.selected_node)
     }
+
+    pub fn get_circuit(ctx: Context<GetSelectedNode>) -> Result<Vec<Pubkey>> {
+        Ok(ctx.accounts.vrf_request.circuit.clone())
+    }
+
+    pub fn get_recent_requests(ctx: Context<GetRecentRequests>) -> Result<Vec<u64>> {
+        Ok(ctx.accounts.user_state.recent_requests.clone())
+    }
+
+    pub fn close_request(ctx: Context<CloseRequest>) -> Result<()> {
+        let request = &ctx.accounts.vrf_request;
+        require!(request.is_fulfilled, ErrorCode::RequestNotFulfilled);
+
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.recent_requests.retain(|&nonce| nonce != request.nonce);
+
+        if request.fee_mint.is_some() {
+            let (Some(fee_escrow), Some(token_program)) =
+                (&ctx.accounts.fee_escrow, &ctx.accounts.token_program)
+            else {
+                return err!(ErrorCode::MissingFeeAccounts);
+            };
+            close_escrow(
+                token_program,
+                fee_escrow,
+                ctx.accounts.user.to_account_info(),
+                &request.key(),
+                ctx.bumps.fee_escrow.unwrap(),
+            )?;
+        }
+
+        emit!(RequestClosed {
+            user: request.user,
+            nonce: request.nonce,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_request(ctx: Context<CancelRequest>) -> Result<()> {
+        let request = &ctx.accounts.vrf_request;
+        let clock = Clock::get()?;
+        require!(!request.is_fulfilled, ErrorCode::AlreadyFulfilled);
+        require!(clock.slot > request.expires_at_slot, ErrorCode::RequestNotExpired);
+
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.recent_requests.retain(|&nonce| nonce != request.nonce);
+
+        // SOL fees leave with the request account's lamports when it closes.
+        if request.fee_mint.is_some() {
+            let (Some(fee_escrow), Some(user_token_account), Some(token_program)) = (
+                &ctx.accounts.fee_escrow,
+                &ctx.accounts.user_token_account,
+                &ctx.accounts.token_program,
+            ) else {
+                return err!(ErrorCode::MissingFeeAccounts);
+            };
+            let bump = ctx.bumps.fee_escrow.unwrap();
+            transfer_from_escrow(
+                token_program,
+                fee_escrow,
+                user_token_account.to_account_info(),
+                &request.key(),
+                bump,
+                request.fee,
+            )?;
+            close_escrow(
+                token_program,
+                fee_escrow,
+                ctx.accounts.user.to_account_info(),
+                &request.key(),
+                bump,
+            )?;
+        }
+
+        emit!(RequestCancelled {
+            user: request.user,
+            nonce: request.nonce,
+            refunded_fee: request.fee,
+            fee_mint: request.fee_mint,
+            expires_at_slot: request.expires_at_slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+fn transfer_from_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    fee_escrow: &Account<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    request_key: &Pubkey,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"vrf_escrow",
+        request_key.as_ref(),
+        &[bump],
+    ]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        Transfer {
+            from: fee_escrow.to_account_info(),
+            to,
+            authority: fee_escrow.to_account_info(),
+        },
+        signer_seeds,
+    );
+    token::transfer(transfer_ctx, amount)
+}
+
+fn close_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    fee_escrow: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    request_key: &Pubkey,
+    bump: u8,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"vrf_escrow",
+        request_key.as_ref(),
+        &[bump],
+    ]];
+
+    let close_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: fee_escrow.to_account_info(),
+            destination,
+            authority: fee_escrow.to_account_info(),
+        },
+        signer_seeds,
+    );
+    token::close_account(close_ctx)
+}
+
+/// Splits remaining accounts into the `page_count` directory pages and the
+/// trailing callback program and its accounts, which may be left off.
+fn split_remaining_accounts<'a, 'info>(
+    request: &VrfRequest,
+    accounts: &'a [AccountInfo<'info>],
+    page_count: u32,
+) -> Result<(&'a [AccountInfo<'info>], &'a [AccountInfo<'info>])> {
+    let page_count = page_count as usize;
+    require!(page_count > 0, ErrorCode::EmptyNodePool);
+    require!(accounts.len() >= page_count, ErrorCode::IncompleteNodePool);
+
+    let (pages, callback_accounts) = accounts.split_at(page_count);
+    require!(
+        callback_accounts.is_empty() || request.callback.is_some(),
+        ErrorCode::CallbackAccountMismatch
+    );
+    Ok((pages, callback_accounts))
+}
+
+/// Returns the callback to invoke now, or marks it pending for
+/// `retry_callback` when the fulfiller left its accounts off. A consumer
+/// whose callback fails therefore cannot stop the request being fulfilled.
+fn defer_callback_if_absent(
+    request: &mut VrfRequest,
+    callback_accounts: &[AccountInfo],
+) -> Option<Callback> {
+    let callback = request.callback.clone()?;
+    if callback_accounts.is_empty() {
+        request.callback_pending = true;
+        return None;
+    }
+    Some(callback)
+}
+
+/// Selects the circuit from `directory_pages` and records it on the request.
+fn apply_selection<'info>(
+    request: &mut VrfRequest,
+    randomness: [u8; 32],
+    directory_pages: &'info [AccountInfo<'info>],
+    stake_weighted: bool,
+    registry: &GlobalRegistry,
+) -> Result<Vec<Pubkey>> {
+    let candidates = load_candidates(directory_pages, stake_weighted, registry, request)?;
+
+    let circuit = select_circuit(
+        &randomness,
+        candidates,
+        request.hop_count,
+        &request.constraints,
+    )?;
+
+    request.selected_node = Some(circuit[0]);
+    request.circuit = circuit.clone();
+    request.randomness = randomness;
+    request.is_fulfilled = true;
+
+    Ok(circuit)
+}
+
+/// Returns the hash of the earliest recorded slot at or after `target_slot`
+/// from raw SlotHashes data (`u64` count, then `(slot, hash)` newest first).
+fn find_slot_hash(data: &[u8], target_slot: u64) -> Result<(u64, [u8; 32])> {
+    require!(data.len() >= 8, ErrorCode::InvalidSlotHashes);
+    let mut len_bytes = [0u8; 8];
+    len_bytes.copy_from_slice(&data[..8]);
+    let len = u64::from_le_bytes(len_bytes) as usize;
+    require!(
+        data.len() >= len.checked_mul(SLOT_HASH_ENTRY_LEN).unwrap().checked_add(8).unwrap(),
+        ErrorCode::InvalidSlotHashes
+    );
+
+    let entry = |index: usize| -> (u64, [u8; 32]) {
+        let start = 8 + index * SLOT_HASH_ENTRY_LEN;
+        let mut slot = [0u8; 8];
+        let mut hash = [0u8; 32];
+        slot.copy_from_slice(&data[start..start + 8]);
+        hash.copy_from_slice(&data[start + 8..start + SLOT_HASH_ENTRY_LEN]);
+        (u64::from_le_bytes(slot), hash)
+    };
+
+    // Count the entries with slot >= target; the last of them is the answer.
+    let (mut low, mut high) = (0usize, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if entry(mid).0 >= target_slot {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    require!(low > 0, ErrorCode::RevealTooEarly);
+    let (slot, hash) = entry(low - 1);
+    // Every retained slot is newer than the target: it has aged out.
+    require!(
+        low < len || slot == target_slot,
+        ErrorCode::RevealWindowExpired
+    );
+
+    Ok((slot, hash))
+}
+
+/// Invokes the consumer program with `discriminator || borsh(data)`. The
+/// request PDA signs so the consumer can check the call came from this program.
+fn invoke_callback(
+    callback: &Callback,
+    accounts: &[AccountInfo],
+    data: &VrfCallbackData,
+    request_bump: u8,
+) -> Result<()> {
+    let (program, metas) = accounts.split_first().ok_or(ErrorCode::CallbackAccountMismatch)?;
+    require_keys_eq!(program.key(), callback.program_id, ErrorCode::CallbackAccountMismatch);
+    require!(program.executable, ErrorCode::InvalidCallback);
+
+    let mut account_metas = Vec::with_capacity(callback.accounts.len());
+    for (meta, info) in callback.accounts.iter().zip(metas) {
+        require_keys_eq!(info.key(), meta.pubkey, ErrorCode::CallbackAccountMismatch);
+        account_metas.push(AccountMeta {
+            pubkey: meta.pubkey,
+            is_signer: meta.is_signer,
+            is_writable: meta.is_writable,
+        });
+    }
+
+    let mut instruction_data = callback.discriminator.to_vec();
+    data.serialize(&mut instruction_data)?;
+
+    let instruction = Instruction {
+        program_id: callback.program_id,
+        accounts: account_metas,
+        data: instruction_data,
+    };
+
+    let nonce_bytes = data.nonce.to_le_bytes();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"vrf_request",
+        data.user.as_ref(),
+        nonce_bytes.as_ref(),
+        &[request_bump],
+    ]];
+
+    invoke_signed(&instruction, accounts, signer_seeds)?;
+
+    emit!(CallbackInvoked {
+        user: data.user,
+        nonce: data.nonce,
+        program_id: callback.program_id,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+struct Candidate {
+    node: Pubkey,
+    operator: Pubkey,
+    country: [u8; 2],
+    weight: u64,
+}
+
+/// Reads the candidate pool from node-registry directory pages. Every page
+/// has to be passed, in index order, so the fulfiller cannot shape the outcome
+/// by leaving nodes out; nodes registered after the request are never
+/// selected, so registering one cannot change it either.
+fn load_candidates<'info>(
+    pages: &'info [AccountInfo<'info>],
+    stake_weighted: bool,
+    registry: &GlobalRegistry,
+    request: &VrfRequest,
+) -> Result<Vec<Candidate>> {
+    require!(
+        pages.len() == registry.directory_pages as usize,
+        ErrorCode::IncompleteNodePool
+    );
+
+    let min_stake = registry.config.min_stake;
+    let filter = &request.filter;
+    let mut candidates = Vec::new();
+    let mut filtered_out = 0u32;
+
+    for (index, info) in pages.iter().enumerate() {
+        let (expected, _) = Pubkey::find_program_address(
+            &[b"directory", (index as u32).to_le_bytes().as_ref()],
+            &node_registry::ID,
+        );
+        require_keys_eq!(info.key(), expected, ErrorCode::InvalidDirectoryPage);
+        let loader = AccountLoader::<DirectoryPage>::try_from(info)?;
+        let page = loader.load()?;
+
+        for entry in page.entries.iter().take(page.count as usize) {
+            if entry.node == Pubkey::default()
+                || entry.is_active == 0
+                || entry.stake < min_stake
+                || entry.registered_at > request.timestamp
+            {
+                continue;
+            }
+
+            // Reputation scaled by the registry's rolling uptime figure.
+            let mut weight = (entry.reputation as u64)
+                .checked_mul(entry.uptime_percentage as u64).unwrap();
+            if stake_weighted {
+                let multiplier = entry.stake
+                    .checked_add(entry.delegated_stake).unwrap()
+                    .checked_div(min_stake).unwrap()
+                    .min(MAX_STAKE_MULTIPLIER);
+                weight = weight.checked_mul(multiplier).unwrap();
+            }
+            if weight == 0 {
+                continue;
+            }
+
+            if !filter.allows(entry) {
+                filtered_out = filtered_out.checked_add(1).unwrap();
+                continue;
+            }
+
+            candidates.push(Candidate {
+                node: entry.node,
+                operator: entry.operator,
+                country: entry.country_code,
+                weight,
+            });
+        }
+    }
+
+    if candidates.is_empty() {
+        if filtered_out > 0 {
+            return err!(ErrorCode::NoNodeMatchesFilter);
+        }
+        return err!(ErrorCode::NoEligibleNodes);
+    }
+
+    Ok(candidates)
+}
+
+/// Picks `hop_count` distinct nodes by weighted sampling without replacement,
+/// drawing a fresh value for each hop from the single VRF output.
+fn select_circuit(
+    randomness: &[u8; 32],
+    mut pool: Vec<Candidate>,
+    hop_count: u8,
+    constraints: &CircuitConstraints,
+) -> Result<Vec<Pubkey>> {
+    let mut chosen: Vec<Candidate> = Vec::with_capacity(hop_count as usize);
+    let mut stream = RandomStream::new(*randomness);
+
+    for _ in 0..hop_count {
+        let eligible: Vec<usize> = (0..pool.len())
+            .filter(|&i| chosen.iter().all(|prior| constraints.allows(prior, &pool[i])))
+            .collect();
+        require!(!eligible.is_empty(), ErrorCode::CircuitUnsatisfiable);
+
+        let weights: Vec<u64> = eligible.iter().map(|&i| pool[i].weight).collect();
+        let index = select_weighted_node(&mut stream, &weights)?;
+        chosen.push(pool.remove(eligible[index]));
+    }
+
+    Ok(chosen.into_iter().map(|hop| hop.node).collect())
+}
+
+/// Deterministic stream of 64-bit words. The first block is the VRF output
+/// itself; later blocks are `sha256(seed || counter)`.
+struct RandomStream {
+    seed: [u8; 32],
+    block: [u8; 32],
+    counter: u64,
+    offset: usize,
+}
+
+impl RandomStream {
+    fn new(seed: [u8; 32]) -> Self {
+        Self {
+            seed,
+            block: seed,
+            counter: 0,
+            offset: 0,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.offset == self.block.len() {
+            self.counter = self.counter.checked_add(1).unwrap();
+            self.block = hashv(&[&self.seed, &self.counter.to_le_bytes()]).to_bytes();
+            self.offset = 0;
+        }
+
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&self.block[self.offset..self.offset + 8]);
+        self.offset = self.offset.checked_add(8).unwrap();
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Uniform value in `[0, bound)`. Words below `2^64 mod bound` are
+    /// rejected so every residue has exactly the same number of preimages.
+    fn next_below(&mut self, bound: u64) -> u64 {
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let value = self.next_u64();
+            if value >= threshold {
+                return value % bound;
+            }
+        }
+    }
 }
 
-fn select_weighted_node(
-    random_value: u64,
-    nodes: &[Pubkey],
-    weights: &[u8],
-) -> Result<Pubkey> {
-    let total_weight: u64 = weights.iter().map(|&w| w as u64).sum();
+fn select_weighted_node(stream: &mut RandomStream, weights: &[u64]) -> Result<usize> {
+    let total_weight = weights
+        .iter()
+        .try_fold(0u64, |total, &weight| total.checked_add(weight))
+        .ok_or(ErrorCode::WeightOverflow)?;
     require!(total_weight > 0, ErrorCode::ZeroTotalWeight);
 
-    let target = random_value % total_weight;
+    let target = stream.next_below(total_weight);
     let mut cumulative = 0u64;
 
     for (i, &weight) in weights.iter().enumerate() {
-        cumulative = cumulative.checked_add(weight as u64).unwrap();
+        cumulative = cumulative.checked_add(weight).unwrap();
         if target < cumulative {
-            return Ok(nodes[i]);
+            return Ok(i);
         }
     }
 
     #[allow(clippy::arithmetic_side_effects)]
-    Ok(nodes[nodes.len() - 1])
+    Ok(weights.len() - 1)
+}
+
+/// Verifies an ECVRF-EDWARDS25519-SHA512-TAI proof of `alpha` under
+/// `public_key` and returns the 64-byte VRF output (beta).
+fn ecvrf_verify(
+    public_key: &[u8; 32],
+    proof: &[u8; ECVRF_PROOF_LEN],
+    alpha: &[u8],
+) -> Result<[u8; 64]> {
+    let y = PodEdwardsPoint(*public_key);
+    let cofactor = PodScalar(ED25519_COFACTOR);
+
+    let cleared_y = multiply_edwards(&cofactor, &y).ok_or(ErrorCode::InvalidVrfPublicKey)?;
+    require!(cleared_y.0 != ED25519_IDENTITY, ErrorCode::InvalidVrfPublicKey);
+
+    let mut gamma_bytes = [0u8; 32];
+    gamma_bytes.copy_from_slice(&proof[..32]);
+    let gamma = PodEdwardsPoint(gamma_bytes);
+    require!(validate_edwards(&gamma), ErrorCode::InvalidVrfProof);
+
+    let mut c_bytes = [0u8; 32];
+    c_bytes[..ECVRF_CHALLENGE_LEN].copy_from_slice(&proof[32..48]);
+    let c = PodScalar(c_bytes);
+
+    // Non-canonical s (s >= L) is rejected by the scalar multiplication.
+    let mut s_bytes = [0u8; 32];
+    s_bytes.copy_from_slice(&proof[48..]);
+    let s = PodScalar(s_bytes);
+
+    let h = ecvrf_encode_to_curve(&y, alpha)?;
+
+    let s_b = multiply_edwards(&s, &PodEdwardsPoint(ED25519_BASEPOINT))
+        .ok_or(ErrorCode::InvalidVrfProof)?;
+    let c_y = multiply_edwards(&c, &y).ok_or(ErrorCode::InvalidVrfProof)?;
+    let u = subtract_edwards(&s_b, &c_y).ok_or(ErrorCode::InvalidVrfProof)?;
+
+    let s_h = multiply_edwards(&s, &h).ok_or(ErrorCode::InvalidVrfProof)?;
+    let c_gamma = multiply_edwards(&c, &gamma).ok_or(ErrorCode::InvalidVrfProof)?;
+    let v = subtract_edwards(&s_h, &c_gamma).ok_or(ErrorCode::InvalidVrfProof)?;
+
+    let challenge = Sha512::new()
+        .chain_update([ECVRF_SUITE_STRING, 0x02])
+        .chain_update(y.0)
+        .chain_update(h.0)
+        .chain_update(gamma.0)
+        .chain_update(u.0)
+        .chain_update(v.0)
+        .chain_update([0x00])
+        .finalize();
+    require!(
+        challenge[..ECVRF_CHALLENGE_LEN] == proof[32..48],
+        ErrorCode::InvalidVrfProof
+    );
+
+    let cleared_gamma = multiply_edwards(&cofactor, &gamma).ok_or(ErrorCode::InvalidVrfProof)?;
+    let beta = Sha512::new()
+        .chain_update([ECVRF_SUITE_STRING, 0x03])
+        .chain_update(cleared_gamma.0)
+        .chain_update([0x00])
+        .finalize();
+
+    let mut output = [0u8; 64];
+    output.copy_from_slice(&beta);
+    Ok(output)
+}
+
+/// Try-and-increment hash to curve, salted with the public key.
+fn ecvrf_encode_to_curve(y: &PodEdwardsPoint, alpha: &[u8]) -> Result<PodEdwardsPoint> {
+    for ctr in 0..=u8::MAX {
+        let hash = Sha512::new()
+            .chain_update([ECVRF_SUITE_STRING, 0x01])
+            .chain_update(y.0)
+            .chain_update(alpha)
+            .chain_update([ctr, 0x00])
+            .finalize();
+
+        let mut candidate = [0u8; 32];
+        candidate.copy_from_slice(&hash[..32]);
+        let candidate = PodEdwardsPoint(candidate);
+
+        if validate_edwards(&candidate) {
+            return multiply_edwards(&PodScalar(ED25519_COFACTOR), &candidate)
+                .ok_or_else(|| ErrorCode::InvalidVrfProof.into());
+        }
+    }
+
+    err!(ErrorCode::HashToCurveFailed)
 }
 
 #[derive(Accounts)]
-pub struct RequestRandomNode<'info> {
+pub struct InitializeVrfConfig<'info> {
     #[account(
         init,
-        payer = user,
-        space = 8 + VrfRequest::LEN,
-        seeds = [b"vrf_request", user.key().as_ref()],
+        payer = admin,
+        space = 8 + VrfConfig::LEN,
+        seeds = [b"vrf_config"],
         bump
     )]
-    pub vrf_request: Account<'info, VrfRequest>,
+    pub vrf_config: Account<'info, VrfConfig>,
     
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub admin: Signer<'info>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct FulfillRandomSelection<'info> {
+pub struct ManageOracles<'info> {
     #[account(
         mut,
-        seeds = [b"vrf_request", vrf_request.user.as_ref()],
-        bump
+        seeds = [b"vrf_config"],
+        bump,
+        has_one = admin
     )]
-    pub vrf_request: Account<'info, VrfRequest>,
+    pub vrf_config: Account<'info, VrfConfig>,
     
-    /// CHECK: VRF authority
-    pub vrf_authority: Signer<'info>,
+    pub admin: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct GetSelectedNode<'info> {
+#[instruction(nonce: u64)]
+pub struct RequestRandomNode<'info> {
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VrfUserState::LEN,
+        seeds = [b"vrf_user", user.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, VrfUserState>,
+    
     #[account(
-        seeds = [b"vrf_request", vrf_request.user.as_ref()],
+        init,
+        payer = user,
+        space = 8 + VrfRequest::LEN,
+        seeds = [b"vrf_request", user.key().as_ref(), nonce.to_le_bytes().as_ref()],
         bump
     )]
     pub vrf_request: Account<'info, VrfRequest>,
     
+    #[account(
+        seeds = [b"vrf_config"],
+        bump
+    )]
+    pub vrf_config: Account<'info, VrfConfig>,
+    
+    #[account(
+        init,
+        payer = user,
+        token::mint = fee_mint,
+        token::authority = fee_escrow,
+        seeds = [b"vrf_escrow", vrf_request.key().as_ref()],
+        bump
+    )]
+    pub fee_escrow: Option<Account<'info, TokenAccount>>,
+    
+    pub fee_mint: Option<Account<'info, Mint>>,
+    
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
     pub user: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct VrfRequest {
-    pub user: Pubkey,
+#[derive(Accounts)]
+pub struct FulfillRandomSelection<'info> {
+    #[account(
+        mut,
+        seeds = [b"vrf_request", vrf_request.user.as_ref(), vrf_request.nonce.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub vrf_request: Account<'info, VrfRequest>,
+    
+    #[account(
+        mut,
+        seeds = [b"vrf_config"],
+        bump
+    )]
+    pub vrf_config: Account<'info, VrfConfig>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump,
+        seeds::program = node_registry::ID
+    )]
+    pub node_registry: Account<'info, GlobalRegistry>,
+    
+    /// CHECK: VRF authority; its ed25519 key is the ECVRF public key
+    #[account(mut)]
+    pub vrf_authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"vrf_escrow", vrf_request.key().as_ref()],
+        bump
+    )]
+    pub fee_escrow: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub oracle_token_account: Option<Account<'info, TokenAccount>>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct RevealSelection<'info> {
+    #[account(
+        mut,
+        seeds = [b"vrf_request", user.key().as_ref(), vrf_request.nonce.to_le_bytes().as_ref()],
+        bump,
+        has_one = user
+    )]
+    pub vrf_request: Account<'info, VrfRequest>,
+    
+    #[account(
+        seeds = [b"vrf_config"],
+        bump
+    )]
+    pub vrf_config: Account<'info, VrfConfig>,
+    
+    #[account(
+        seeds = [b"registry"],
+        bump,
+        seeds::program = node_registry::ID
+    )]
+    pub node_registry: Account<'info, GlobalRegistry>,
+    
+    /// CHECK: SlotHashes sysvar, read raw to avoid deserializing every entry
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+    
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RetryCallback<'info> {
+    #[account(
+        mut,
+        seeds = [b"vrf_request", vrf_request.user.as_ref(), vrf_request.nonce.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub vrf_request: Account<'info, VrfRequest>,
+}
+
+#[derive(Accounts)]
+pub struct GetSelectedNode<'info> {
+    #[account(
+        seeds = [b"vrf_request", vrf_request.user.as_ref(), vrf_request.nonce.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub vrf_request: Account<'info, VrfRequest>,
+    
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetRecentRequests<'info> {
+    #[account(
+        seeds = [b"vrf_user", user_state.user.as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, VrfUserState>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRequest<'info> {
+    #[account(
+        mut,
+        seeds = [b"vrf_request", user.key().as_ref(), vrf_request.nonce.to_le_bytes().as_ref()],
+        bump,
+        has_one = user,
+        close = user
+    )]
+    pub vrf_request: Account<'info, VrfRequest>,
+    
+    #[account(
+        mut,
+        seeds = [b"vrf_user", user.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, VrfUserState>,
+    
+    #[account(
+        mut,
+        seeds = [b"vrf_escrow", vrf_request.key().as_ref()],
+        bump
+    )]
+    pub fee_escrow: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRequest<'info> {
+    #[account(
+        mut,
+        seeds = [b"vrf_request", user.key().as_ref(), vrf_request.nonce.to_le_bytes().as_ref()],
+        bump,
+        has_one = user,
+        close = user
+    )]
+    pub vrf_request: Account<'info, VrfRequest>,
+    
+    #[account(
+        mut,
+        seeds = [b"vrf_user", user.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, VrfUserState>,
+    
+    #[account(
+        mut,
+        seeds = [b"vrf_escrow", vrf_request.key().as_ref()],
+        bump
+    )]
+    pub fee_escrow: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct VrfConfig {
+    pub admin: Pubkey,
+    pub stake_weighted: bool,
+    pub request_timeout_slots: u64,
+    #[max_len(MAX_ORACLES)]
+    pub oracles: Vec<OracleInfo>,
+}
+
+impl VrfConfig {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 1 + 8 + (4 + MAX_ORACLES * OracleInfo::LEN);
+
+    pub fn find_oracle(&self, oracle: &Pubkey) -> Option<usize> {
+        self.oracles.iter().position(|info| info.oracle == *oracle)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct OracleInfo {
+    pub oracle: Pubkey,
+    pub fulfillment_count: u64,
+    pub total_latency: i64,
+    pub max_latency: i64,
+    pub last_fulfilled_at: i64,
+}
+
+impl OracleInfo {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8;
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct VrfUserState {
+    pub user: Pubkey,
+    pub request_count: u64,
+    #[max_len(MAX_RECENT_REQUESTS)]
+    pub recent_requests: Vec<u64>,
+}
+
+impl VrfUserState {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 8 + (4 + MAX_RECENT_REQUESTS * 8);
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct VrfRequest {
+    pub user: Pubkey,
+    pub nonce: u64,
     pub seed: [u8; 32],
     pub timestamp: i64,
     pub is_fulfilled: bool,
     pub selected_node: Option<Pubkey>,
+    pub randomness: [u8; 32],
+    pub hop_count: u8,
+    pub constraints: CircuitConstraints,
+    #[max_len(MAX_HOPS)]
+    pub circuit: Vec<Pubkey>,
+    pub expires_at_slot: u64,
+    pub fee: u64,
+    pub fee_mint: Option<Pubkey>,
+    pub callback: Option<Callback>,
+    pub commitment: Option<[u8; 32]>,
+    pub request_slot: u64,
+    pub filter: SelectionFilter,
+    /// Set when the request was fulfilled without delivering its callback.
+    pub callback_pending: bool,
 }
 
 impl VrfRequest {
     #[allow(clippy::arithmetic_side_effects)]
-    pub const LEN: usize = 32 + 32 + 8 + 1 + (1 + 32);
+    pub const LEN: usize = 32 + 8 + 32 + 8 + 1 + (1 + 32) + 32 + 1 + CircuitConstraints::LEN
+        + (4 + MAX_HOPS * 32) + 8 + 8 + (1 + 32) + (1 + Callback::LEN) + (1 + 32) + 8 + SelectionFilter::LEN + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RequestParams {
+    pub hop_count: u8,
+    pub constraints: CircuitConstraints,
+    pub fee: u64,
+    pub callback: Option<Callback>,
+    pub commitment: Option<[u8; 32]>,
+    pub filter: SelectionFilter,
+}
+
+/// Per-request restrictions applied to every hop. Country codes are
+/// upper-case ISO 3166-1 alpha-2; empty lists impose no restriction.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, InitSpace)]
+pub struct SelectionFilter {
+    #[max_len(MAX_FILTER_COUNTRIES)]
+    pub allowed_countries: Vec<[u8; 2]>,
+    #[max_len(MAX_FILTER_COUNTRIES)]
+    pub denied_countries: Vec<[u8; 2]>,
+    pub min_bandwidth_gbps: u16,
+    pub min_reputation: u8,
+    #[max_len(MAX_EXCLUDED_OPERATORS)]
+    pub excluded_operators: Vec<Pubkey>,
+}
+
+impl SelectionFilter {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = (4 + MAX_FILTER_COUNTRIES * 2) + (4 + MAX_FILTER_COUNTRIES * 2)
+        + 2 + 1 + (4 + MAX_EXCLUDED_OPERATORS * 32);
+
+    fn validate(&self) -> Result<()> {
+        require!(
+            self.allowed_countries.len() <= MAX_FILTER_COUNTRIES
+                && self.denied_countries.len() <= MAX_FILTER_COUNTRIES
+                && self.excluded_operators.len() <= MAX_EXCLUDED_OPERATORS,
+            ErrorCode::InvalidFilter
+        );
+        require!(self.min_reputation <= 100, ErrorCode::InvalidFilter);
+        Ok(())
+    }
+
+    fn allows(&self, entry: &DirectoryEntry) -> bool {
+        let listed = |codes: &[[u8; 2]]| codes.contains(&entry.country_code);
+
+        (self.allowed_countries.is_empty() || listed(&self.allowed_countries))
+            && !listed(&self.denied_countries)
+            && entry.bandwidth_gbps >= self.min_bandwidth_gbps
+            && entry.reputation >= self.min_reputation
+            && !self.excluded_operators.contains(&entry.operator)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct Callback {
+    pub program_id: Pubkey,
+    pub discriminator: [u8; 8],
+    #[max_len(MAX_CALLBACK_ACCOUNTS)]
+    pub accounts: Vec<CallbackAccountMeta>,
+}
+
+impl Callback {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 8 + (4 + MAX_CALLBACK_ACCOUNTS * CallbackAccountMeta::LEN);
+
+    fn validate(&self, request: &Pubkey) -> Result<()> {
+        require!(
+            self.accounts.len() <= MAX_CALLBACK_ACCOUNTS,
+            ErrorCode::InvalidCallback
+        );
+        require!(self.program_id != crate::ID, ErrorCode::InvalidCallback);
+        // Only the request PDA can sign, since it is the only key we hold.
+        require!(
+            self.accounts
+                .iter()
+                .all(|meta| !meta.is_signer || meta.pubkey == *request),
+            ErrorCode::InvalidCallback
+        );
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct CallbackAccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl CallbackAccountMeta {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 32 + 1 + 1;
+}
+
+/// Payload appended to the callback discriminator.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct VrfCallbackData {
+    pub user: Pubkey,
+    pub nonce: u64,
+    pub circuit: Vec<Pubkey>,
+    pub randomness: [u8; 32],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct CircuitConstraints {
+    pub distinct_countries: bool,
+    pub distinct_operators: bool,
+}
+
+impl CircuitConstraints {
+    #[allow(clippy::arithmetic_side_effects)]
+    pub const LEN: usize = 1 + 1;
+
+    fn allows(&self, chosen: &Candidate, candidate: &Candidate) -> bool {
+        !(self.distinct_countries && chosen.country == candidate.country
+            || self.distinct_operators && chosen.operator == candidate.operator)
+    }
+}
+
+#[event]
+pub struct VrfConfigInitialized {
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OracleAdded {
+    pub oracle: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OracleRemoved {
+    pub oracle: Pubkey,
+    pub fulfillment_count: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OracleRotated {
+    pub old_oracle: Pubkey,
+    pub new_oracle: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeWeightingUpdated {
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RequestTimeoutUpdated {
+    pub old_timeout_slots: u64,
+    pub new_timeout_slots: u64,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct VrfRequested {
     pub user: Pubkey,
+    pub nonce: u64,
+    pub hop_count: u8,
+    pub fee: u64,
+    pub fee_mint: Option<Pubkey>,
+    pub expires_at_slot: u64,
     pub timestamp: i64,
 }
 
 #[event]
 pub struct NodeSelected {
     pub user: Pubkey,
+    pub nonce: u64,
     pub selected_node: Pubkey,
+    pub circuit: Vec<Pubkey>,
+    pub vrf_authority: Pubkey,
+    pub randomness: [u8; 32],
+    pub latency: i64,
+    pub fee_paid: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RequestClosed {
+    pub user: Pubkey,
+    pub nonce: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RequestCancelled {
+    pub user: Pubkey,
+    pub nonce: u64,
+    pub refunded_fee: u64,
+    pub fee_mint: Option<Pubkey>,
+    pub expires_at_slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NodeRevealed {
+    pub user: Pubkey,
+    pub nonce: u64,
+    pub selected_node: Pubkey,
+    pub circuit: Vec<Pubkey>,
+    pub slot: u64,
+    pub randomness: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CallbackInvoked {
+    pub user: Pubkey,
+    pub nonce: u64,
+    pub program_id: Pubkey,
     pub timestamp: i64,
 }
 
 #[error_code]
 pub enum ErrorCode {
-    #[msg("Node pool and reputation weights have mismatched lengths")]
-    MismatchedLengths,
     #[msg("Node pool cannot be empty")]
     EmptyNodePool,
     #[msg("VRF request already fulfilled")]
     AlreadyFulfilled,
     #[msg("Total weight cannot be zero")]
     ZeroTotalWeight,
+    #[msg("VRF public key is not a valid prime-order point")]
+    InvalidVrfPublicKey,
+    #[msg("VRF proof failed verification")]
+    InvalidVrfProof,
+    #[msg("Hash to curve found no valid point")]
+    HashToCurveFailed,
+    #[msg("Signer is not a registered VRF oracle")]
+    UnauthorizedOracle,
+    #[msg("Oracle is already registered")]
+    OracleAlreadyRegistered,
+    #[msg("Oracle is not registered")]
+    OracleNotRegistered,
+    #[msg("Oracle registry is full")]
+    OracleRegistryFull,
+    #[msg("Account is not the expected node-registry directory page")]
+    InvalidDirectoryPage,
+    #[msg("Every directory page must be passed to selection")]
+    IncompleteNodePool,
+    #[msg("No active, sufficiently staked node in the candidate pool")]
+    NoEligibleNodes,
+    #[msg("Hop count must be between 1 and 3")]
+    InvalidHopCount,
+    #[msg("Not enough eligible nodes to build a circuit under the requested constraints")]
+    CircuitUnsatisfiable,
+    #[msg("Total candidate weight overflows")]
+    WeightOverflow,
+    #[msg("Request nonce does not match the user's next request number")]
+    InvalidNonce,
+    #[msg("VRF request has not been fulfilled yet")]
+    RequestNotFulfilled,
+    #[msg("Request timeout must be greater than 0 slots")]
+    InvalidTimeout,
+    #[msg("VRF request has expired")]
+    RequestExpired,
+    #[msg("VRF request has not expired yet")]
+    RequestNotExpired,
+    #[msg("Fee escrow accounts are missing or incomplete")]
+    MissingFeeAccounts,
+    #[msg("Token fee must be greater than 0")]
+    InvalidFee,
+    #[msg("Callback spec is invalid")]
+    InvalidCallback,
+    #[msg("Callback accounts do not match the registered callback")]
+    CallbackAccountMismatch,
+    #[msg("Request must be completed through its other fulfillment path")]
+    WrongFulfillmentMode,
+    #[msg("Revealed secret does not match the commitment")]
+    CommitmentMismatch,
+    #[msg("Target slot hash is not available yet")]
+    RevealTooEarly,
+    #[msg("Target slot hash is no longer in the SlotHashes sysvar")]
+    RevealWindowExpired,
+    #[msg("SlotHashes sysvar data is malformed")]
+    InvalidSlotHashes,
+    #[msg("Selection filter exceeds its limits or has an invalid reputation")]
+    InvalidFilter,
+    #[msg("No eligible node satisfies the request's selection filter")]
+    NoNodeMatchesFilter,
+    #[msg("Request has no undelivered callback")]
+    CallbackNotPending,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: u32 = 100_000;
+
+    fn seed(i: u32) -> [u8; 32] {
+        hashv(&[b"veilpool-vrf-test", &i.to_le_bytes()]).to_bytes()
+    }
+
+    fn candidate(index: u8, operator: u8, country: &str, weight: u64) -> Candidate {
+        Candidate {
+            node: Pubkey::new_from_array([index; 32]),
+            operator: Pubkey::new_from_array([operator; 32]),
+            country: country.as_bytes().try_into().unwrap(),
+            weight,
+        }
+    }
+
+    /// Pearson chi-square statistic of observed counts against weights.
+    fn chi_square(counts: &[u64], weights: &[u64]) -> f64 {
+        let total_weight: u64 = weights.iter().sum();
+        let samples: u64 = counts.iter().sum();
+        counts
+            .iter()
+            .zip(weights)
+            .filter(|(_, &weight)| weight > 0)
+            .map(|(&observed, &weight)| {
+                let expected = samples as f64 * weight as f64 / total_weight as f64;
+                let diff = observed as f64 - expected;
+                diff * diff / expected
+            })
+            .sum()
+    }
+
+    #[test]
+    fn selection_frequencies_match_weights() {
+        let weights = [100u64, 75, 50, 25, 10, 1];
+        let mut counts = [0u64; 6];
+
+        for i in 0..SAMPLES {
+            let mut stream = RandomStream::new(seed(i));
+            counts[select_weighted_node(&mut stream, &weights).unwrap()] += 1;
+        }
+
+        // 5 degrees of freedom, p = 0.001.
+        let statistic = chi_square(&counts, &weights);
+        assert!(statistic < 20.52, "chi-square {statistic} for {counts:?}");
+    }
+
+    #[test]
+    fn uniform_weights_are_uniform() {
+        let weights = [1u64; 7];
+        let mut counts = [0u64; 7];
+
+        for i in 0..SAMPLES {
+            let mut stream = RandomStream::new(seed(i));
+            counts[select_weighted_node(&mut stream, &weights).unwrap()] += 1;
+        }
+
+        // 6 degrees of freedom, p = 0.001.
+        let statistic = chi_square(&counts, &weights);
+        assert!(statistic < 22.46, "chi-square {statistic} for {counts:?}");
+    }
+
+    #[test]
+    fn zero_weight_nodes_are_never_selected() {
+        let weights = [0u64, 3, 0, 5, 0];
+
+        for i in 0..1_000 {
+            let mut stream = RandomStream::new(seed(i));
+            let index = select_weighted_node(&mut stream, &weights).unwrap();
+            assert!(weights[index] > 0);
+        }
+    }
+
+    #[test]
+    fn zero_total_weight_is_rejected() {
+        let mut stream = RandomStream::new(seed(0));
+        assert!(select_weighted_node(&mut stream, &[0, 0]).is_err());
+    }
+
+    #[test]
+    fn overflowing_weights_are_rejected() {
+        let mut stream = RandomStream::new(seed(0));
+        assert!(select_weighted_node(&mut stream, &[u64::MAX, 1]).is_err());
+    }
+
+    #[test]
+    fn biased_words_are_rejected() {
+        // For bound = 3 * 2^62 the rejection threshold is 2^64 mod bound = 2^62,
+        // so a leading zero word must be skipped in favour of the next one.
+        let mut seed = [0u8; 32];
+        seed[8..16].copy_from_slice(&(1u64 << 62).to_le_bytes());
+
+        let mut stream = RandomStream::new(seed);
+        assert_eq!(stream.next_below(3 << 62), 1 << 62);
+        assert_eq!(stream.offset, 16);
+    }
+
+    #[test]
+    fn stream_uses_all_bytes_then_expands_with_hash() {
+        let seed = seed(42);
+        let mut stream = RandomStream::new(seed);
+
+        for chunk in seed.chunks(8) {
+            assert_eq!(stream.next_u64(), u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+
+        let expanded = hashv(&[&seed, &1u64.to_le_bytes()]).to_bytes();
+        assert_eq!(stream.next_u64(), u64::from_le_bytes(expanded[..8].try_into().unwrap()));
+    }
+
+    #[test]
+    fn circuit_hops_are_distinct_and_respect_constraints() {
+        let constraints = CircuitConstraints {
+            distinct_countries: true,
+            distinct_operators: true,
+        };
+
+        for i in 0..2_000 {
+            let pool = vec![
+                candidate(1, 1, "US", 100),
+                candidate(2, 1, "DE", 100),
+                candidate(3, 2, "US", 100),
+                candidate(4, 3, "NL", 50),
+                candidate(5, 4, "CH", 10),
+            ];
+            let circuit = select_circuit(&seed(i), pool, 3, &constraints).unwrap();
+
+            assert_eq!(circuit.len(), 3);
+            let hops: Vec<u8> = circuit.iter().map(|node| node.to_bytes()[0]).collect();
+            assert!(!(hops.contains(&1) && hops.contains(&2)), "shared operator in {hops:?}");
+            assert!(!(hops.contains(&1) && hops.contains(&3)), "shared country in {hops:?}");
+        }
+    }
+
+    #[test]
+    fn circuit_fails_when_constraints_cannot_be_met() {
+        let constraints = CircuitConstraints {
+            distinct_countries: true,
+            distinct_operators: false,
+        };
+        let pool = vec![candidate(1, 1, "US", 10), candidate(2, 2, "US", 10)];
+
+        assert!(select_circuit(&seed(0), pool, 2, &constraints).is_err());
+    }
+
+    #[test]
+    fn circuit_sampling_without_replacement_matches_weights() {
+        // P(first = a) = w_a / W, P(second = b | first = a) = w_b / (W - w_a).
+        let weights = [60u64, 30, 10];
+        let mut first = [0u64; 3];
+        let mut second = [0u64; 3];
+
+        for i in 0..SAMPLES {
+            let pool = weights
+                .iter()
+                .enumerate()
+                .map(|(index, &weight)| candidate(index as u8, index as u8, "US", weight))
+                .collect();
+            let circuit =
+                select_circuit(&seed(i), pool, 2, &CircuitConstraints::default()).unwrap();
+            first[circuit[0].to_bytes()[0] as usize] += 1;
+            second[circuit[1].to_bytes()[0] as usize] += 1;
+        }
+
+        let expected_second: Vec<u64> = (0..3)
+            .map(|b| {
+                (0..3)
+                    .filter(|&a| a != b)
+                    .map(|a| {
+                        weights[a] as f64 / 100.0 * weights[b] as f64
+                            / (100 - weights[a]) as f64
+                    })
+                    .sum::<f64>()
+            })
+            .map(|p| (p * 1_000_000.0) as u64)
+            .collect();
+
+        // 2 degrees of freedom, p = 0.001.
+        assert!(chi_square(&first, &weights) < 13.82, "first hop {first:?}");
+        assert!(
+            chi_square(&second, &expected_second) < 13.82,
+            "second hop {second:?}"
+        );
+    }
+
+    fn slot_hashes(slots: &[u64]) -> Vec<u8> {
+        let mut data = (slots.len() as u64).to_le_bytes().to_vec();
+        for &slot in slots {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[slot as u8; 32]);
+        }
+        data
+    }
+
+    #[test]
+    fn slot_hash_lookup_finds_target_or_next_produced_slot() {
+        // Newest first, with slot 103 skipped.
+        let data = slot_hashes(&[106, 105, 104, 102, 101, 100]);
+
+        assert_eq!(find_slot_hash(&data, 102).unwrap(), (102, [102; 32]));
+        assert_eq!(find_slot_hash(&data, 103).unwrap(), (104, [104; 32]));
+        assert_eq!(find_slot_hash(&data, 100).unwrap(), (100, [100; 32]));
+    }
+
+    #[test]
+    fn slot_hash_lookup_rejects_future_and_pruned_slots() {
+        let data = slot_hashes(&[106, 105, 104]);
+
+        assert!(find_slot_hash(&data, 107).is_err());
+        assert!(find_slot_hash(&data, 100).is_err());
+        assert!(find_slot_hash(&data[..20], 105).is_err());
+    }
+
+    fn hex<const N: usize>(s: &str) -> [u8; N] {
+        let bytes: Vec<u8> = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect();
+        bytes.try_into().unwrap()
+    }
+
+    // RFC 9381 Appendix B.3, Example 16 (ECVRF-EDWARDS25519-SHA512-TAI).
+    const RFC_PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const RFC_PROOF: &str = "8657106690b5526245a92b003bb079ccd1a92130477671f6fc01ad16f26f723f\
+        26f8a57ccaed74ee1b190bed1f479d9727d2d0f9b005a6e456a35d4fb0daab12\
+        68a1b0db10836d9826a528ca76567805";
+    const RFC_BETA: &str = "90cf1df3b703cce59e2a35b925d411164068269d7b2d29f3301c03dd757876ff\
+        66b71dda49d2de59d03450451af026798e8f81cd2e333de5cdf4f3e140fdd8ae";
+
+    #[test]
+    fn ecvrf_verify_matches_rfc_vector() {
+        let beta = ecvrf_verify(&hex(RFC_PUBLIC_KEY), &hex(RFC_PROOF), &[]).unwrap();
+        assert_eq!(beta, hex::<64>(RFC_BETA));
+    }
+
+    #[test]
+    fn ecvrf_verify_rejects_tampered_proof() {
+        let public_key = hex(RFC_PUBLIC_KEY);
+        let proof: [u8; ECVRF_PROOF_LEN] = hex(RFC_PROOF);
+
+        // Flip a bit in each of gamma, the challenge and the response.
+        for index in [0, 40, 60] {
+            let mut tampered = proof;
+            tampered[index] ^= 1;
+            assert!(ecvrf_verify(&public_key, &tampered, &[]).is_err());
+        }
+        // A valid proof does not verify against a different input.
+        assert!(ecvrf_verify(&public_key, &proof, b"veilpool").is_err());
+    }
 }
@@ -44,48 +44,81 @@ var __awaiter = (this && this.__awaiter) || function (thisArg, _arguments, P, ge
 Object.defineProperty(exports, "__esModule", { value: true });
 const anchor = __importStar(require("@coral-xyz/anchor"));
 const chai_1 = require("chai");
+const CAPABILITY_RELAY = 1 << 1;
 describe("node-registry", () => {
     const provider = anchor.AnchorProvider.env();
     anchor.setProvider(provider);
     const program = anchor.workspace.NodeRegistry;
     const authority = provider.wallet;
     let globalRegistry;
+    let directoryPage;
     let nodeAccount;
     let stakeVault;
     const operator = anchor.web3.Keypair.generate();
+    const nodeIdentity = anchor.web3.Keypair.generate();
+    const metadata = (countryCode, ip) => ({
+        countryCode: Array.from(Buffer.from(countryCode)),
+        geohash: "9q8yy",
+        endpoints: [
+            {
+                addressKind: { ipv4: {} },
+                address: [...ip, ...new Array(12).fill(0)],
+                port: 443,
+                protocol: { tcp: {} },
+            },
+        ],
+        noisePublicKey: Array.from(anchor.web3.Keypair.generate().publicKey.toBytes()),
+        capabilities: CAPABILITY_RELAY,
+    });
     before(() => __awaiter(void 0, void 0, void 0, function* () {
         [globalRegistry] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("registry")], program.programId);
-        [nodeAccount] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("node"), operator.publicKey.toBuffer()], program.programId);
+        [directoryPage] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("directory"), new anchor.BN(0).toArrayLike(Buffer, "le", 4)], program.programId);
+        [nodeAccount] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("node"), nodeIdentity.publicKey.toBuffer()], program.programId);
         [stakeVault] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("stake"), nodeAccount.toBuffer()], program.programId);
         yield provider.connection.confirmTransaction(yield provider.connection.requestAirdrop(operator.publicKey, 150 * anchor.web3.LAMPORTS_PER_SOL));
     }));
     it("Initializes the registry", () => __awaiter(void 0, void 0, void 0, function* () {
         const tx = yield program.methods
             .initialize()
-            .accounts({
+            .accountsPartial({
             globalRegistry,
             authority: authority.publicKey,
             protocolFeeVault: authority.publicKey,
         })
             .rpc();
+        yield program.methods
+            .createDirectoryPage()
+            .accountsPartial({
+            directoryPage,
+            globalRegistry,
+            payer: authority.publicKey,
+        })
+            .rpc();
         const registryAccount = yield program.account.globalRegistry.fetch(globalRegistry);
         (0, chai_1.expect)(registryAccount.totalNodes).to.equal(0);
+        (0, chai_1.expect)(registryAccount.directoryPages).to.equal(1);
         (0, chai_1.expect)(registryAccount.authority.toString()).to.equal(authority.publicKey.toString());
     }));
     it("Registers a new node", () => __awaiter(void 0, void 0, void 0, function* () {
         const tx = yield program.methods
-            .registerNode("US-WEST-1", "192.168.1.1", 10)
-            .accounts({
+            .registerNode(metadata("US", [192, 168, 1, 1]), 10)
+            .accountsPartial({
             nodeAccount,
+            nodeIdentity: nodeIdentity.publicKey,
+            directoryPage,
             globalRegistry,
             operator: operator.publicKey,
+            stakeTokenVault: null,
+            stakeMint: null,
+            tokenProgram: null,
         })
-            .signers([operator])
+            .signers([operator, nodeIdentity])
             .rpc();
         const node = yield program.account.nodeAccount.fetch(nodeAccount);
         (0, chai_1.expect)(node.operator.toString()).to.equal(operator.publicKey.toString());
-        (0, chai_1.expect)(node.location).to.equal("US-WEST-1");
-        (0, chai_1.expect)(node.reputation).to.equal(100);
+        (0, chai_1.expect)(node.nodeId.toString()).to.equal(nodeIdentity.publicKey.toString());
+        (0, chai_1.expect)(Buffer.from(node.metadata.countryCode).toString()).to.equal("US");
+        (0, chai_1.expect)(node.metadata.endpoints[0].port).to.equal(443);
         (0, chai_1.expect)(node.isActive).to.equal(false);
         const registry = yield program.account.globalRegistry.fetch(globalRegistry);
         (0, chai_1.expect)(registry.totalNodes).to.equal(1);
@@ -94,9 +127,10 @@ describe("node-registry", () => {
         const stakeAmount = 100 * anchor.web3.LAMPORTS_PER_SOL;
         const tx = yield program.methods
             .stakeSol(new anchor.BN(stakeAmount))
-            .accounts({
+            .accountsPartial({
             nodeAccount,
             stakeVault,
+            directoryPage,
             globalRegistry,
             operator: operator.publicKey,
         })
@@ -107,11 +141,14 @@ describe("node-registry", () => {
         (0, chai_1.expect)(node.isActive).to.equal(true);
     }));
     it("Updates node heartbeat", () => __awaiter(void 0, void 0, void 0, function* () {
+        const [nodeEpochPoints] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("node_epoch"), nodeAccount.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, "le", 8)], program.programId);
         const tx = yield program.methods
             .updateHeartbeat(new anchor.BN(50))
-            .accounts({
+            .accountsPartial({
             nodeAccount,
             globalRegistry,
+            directoryPage,
+            nodeEpochPoints,
             operator: operator.publicKey,
         })
             .signers([operator])
@@ -119,38 +156,46 @@ describe("node-registry", () => {
         const node = yield program.account.nodeAccount.fetch(nodeAccount);
         (0, chai_1.expect)(node.totalBandwidthServed.toString()).to.equal("50");
     }));
-    it("Updates reputation", () => __awaiter(void 0, void 0, void 0, function* () {
+    it("Adjusts reputation", () => __awaiter(void 0, void 0, void 0, function* () {
         const tx = yield program.methods
-            .updateReputation(95)
-            .accounts({
+            .adjustReputation(-5, 1)
+            .accountsPartial({
             nodeAccount,
+            directoryPage,
             globalRegistry,
             authority: authority.publicKey,
         })
             .rpc();
         const node = yield program.account.nodeAccount.fetch(nodeAccount);
-        (0, chai_1.expect)(node.reputation).to.equal(95);
+        (0, chai_1.expect)(node.reputationAdjustment).to.equal(-5);
     }));
     it("Fails to stake with insufficient amount", () => __awaiter(void 0, void 0, void 0, function* () {
         const newOperator = anchor.web3.Keypair.generate();
+        const newNodeIdentity = anchor.web3.Keypair.generate();
         yield provider.connection.confirmTransaction(yield provider.connection.requestAirdrop(newOperator.publicKey, 10 * anchor.web3.LAMPORTS_PER_SOL));
-        const [newNodeAccount] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("node"), newOperator.publicKey.toBuffer()], program.programId);
+        const [newNodeAccount] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("node"), newNodeIdentity.publicKey.toBuffer()], program.programId);
         const [newStakeVault] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("stake"), newNodeAccount.toBuffer()], program.programId);
         yield program.methods
-            .registerNode("EU-CENTRAL-1", "10.0.0.1", 5)
-            .accounts({
+            .registerNode(metadata("DE", [10, 0, 0, 1]), 5)
+            .accountsPartial({
             nodeAccount: newNodeAccount,
+            nodeIdentity: newNodeIdentity.publicKey,
+            directoryPage,
             globalRegistry,
             operator: newOperator.publicKey,
+            stakeTokenVault: null,
+            stakeMint: null,
+            tokenProgram: null,
         })
-            .signers([newOperator])
+            .signers([newOperator, newNodeIdentity])
             .rpc();
         try {
             yield program.methods
                 .stakeSol(new anchor.BN(50 * anchor.web3.LAMPORTS_PER_SOL))
-                .accounts({
+                .accountsPartial({
                 nodeAccount: newNodeAccount,
                 stakeVault: newStakeVault,
+                directoryPage,
                 globalRegistry,
                 operator: newOperator.publicKey,
             })
@@ -4,24 +4,47 @@ import { AnchorProvider, Program, Wallet, BN } from '@coral-xyz/anchor';
 import { NodeRegistry } from '../target/types/node_registry';
 import * as anchor from '@coral-xyz/anchor';
 
+const DIRECTORY_PAGE_SIZE = 96;
+const CAPABILITY_RELAY = 1 << 1;
+const CAPABILITY_UDP = 1 << 3;
+
 describe('Node Registry Program', () => {
   let provider: AnchorProvider;
   let program: Program<NodeRegistry>;
   let nodeOperator: Keypair;
+  let nodeIdentity: Keypair;
   let nodeAccount: PublicKey;
+  let globalRegistry: PublicKey;
+
+  const directoryPageAddress = (page: number) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from('directory'), new BN(page).toArrayLike(Buffer, 'le', 4)],
+      program.programId
+    )[0];
+
+  const nodeDirectoryPage = async () => {
+    const nodeData = await program.account.nodeAccount.fetch(nodeAccount);
+    return directoryPageAddress(nodeData.directoryPage);
+  };
 
   beforeAll(async () => {
     try {
       provider = AnchorProvider.env();
       anchor.setProvider(provider);
       program = anchor.workspace.NodeRegistry as Program<NodeRegistry>;
-      
+
       nodeOperator = Keypair.generate();
-      
+      nodeIdentity = Keypair.generate();
+
+      [globalRegistry] = PublicKey.findProgramAddressSync(
+        [Buffer.from('registry')],
+        program.programId
+      );
+
       // Airdrop SOL to operator
       const airdropSignature = await provider.connection.requestAirdrop(
         nodeOperator.publicKey,
-        10 * LAMPORTS_PER_SOL
+        150 * LAMPORTS_PER_SOL
       );
       await provider.connection.confirmTransaction(airdropSignature);
     } catch (error) {
@@ -34,39 +57,95 @@ describe('Node Registry Program', () => {
       console.warn('Skipping test: Solana not available');
       return;
     }
-    const location = 'US-CA-SanFrancisco';
-    const ipAddress = '192.168.1.1';
+    const metadata = {
+      countryCode: Array.from(Buffer.from('US')),
+      geohash: '9q8yy',
+      endpoints: [
+        {
+          addressKind: { ipv4: {} },
+          address: [192, 168, 1, 1, ...new Array(12).fill(0)],
+          port: 51820,
+          protocol: { udp: {} },
+        },
+      ],
+      noisePublicKey: Array.from(Keypair.generate().publicKey.toBytes()),
+      capabilities: CAPABILITY_RELAY | CAPABILITY_UDP,
+    };
     const bandwidthGbps = 10;
 
+    // Nodes are keyed by their identity key, not the operator wallet.
     [nodeAccount] = PublicKey.findProgramAddressSync(
-      [Buffer.from('node'), nodeOperator.publicKey.toBuffer()],
+      [Buffer.from('node'), nodeIdentity.publicKey.toBuffer()],
       program.programId
     );
 
-    const [globalRegistry] = PublicKey.findProgramAddressSync(
-      [Buffer.from('registry')],
-      program.programId
-    );
+    // Registration appends to the newest directory page, creating it if full.
+    const registry = await program.account.globalRegistry.fetch(globalRegistry);
+    const page = Math.floor(registry.directoryLen.toNumber() / DIRECTORY_PAGE_SIZE);
+    const directoryPage = directoryPageAddress(page);
+    if (page >= registry.directoryPages) {
+      await program.methods
+        .createDirectoryPage()
+        .accountsPartial({
+          directoryPage,
+          globalRegistry,
+          payer: nodeOperator.publicKey,
+        })
+        .signers([nodeOperator])
+        .rpc();
+    }
 
     const tx = await program.methods
-      .registerNode(location, ipAddress, bandwidthGbps)
+      .registerNode(metadata, bandwidthGbps)
       .accountsPartial({
         nodeAccount: nodeAccount,
+        nodeIdentity: nodeIdentity.publicKey,
+        directoryPage,
         globalRegistry: globalRegistry,
         operator: nodeOperator.publicKey,
+        stakeTokenVault: null,
+        stakeMint: null,
+        tokenProgram: null,
       })
-      .signers([nodeOperator])
+      .signers([nodeOperator, nodeIdentity])
       .rpc();
 
     console.log('Register node tx:', tx);
 
     const nodeData = await program.account.nodeAccount.fetch(nodeAccount);
     expect(nodeData.operator.toString()).toBe(nodeOperator.publicKey.toString());
-    expect(nodeData.location).toBe(location);
-    expect(nodeData.ipAddress).toBe(ipAddress);
+    expect(nodeData.nodeId.toString()).toBe(nodeIdentity.publicKey.toString());
+    expect(Buffer.from(nodeData.metadata.countryCode).toString()).toBe('US');
+    expect(nodeData.metadata.geohash).toBe(metadata.geohash);
+    expect(nodeData.metadata.endpoints).toHaveLength(1);
+    expect(nodeData.metadata.endpoints[0].port).toBe(51820);
     expect(nodeData.bandwidthGbps).toBe(bandwidthGbps);
   });
 
+  it('Stakes SOL to activate the node', async () => {
+    if (!provider || !program) {
+      console.warn('Skipping test: Solana not available');
+      return;
+    }
+    const stakeAmount = new BN(100 * LAMPORTS_PER_SOL);
+
+    const tx = await program.methods
+      .stakeSol(stakeAmount)
+      .accountsPartial({
+        nodeAccount: nodeAccount,
+        directoryPage: await nodeDirectoryPage(),
+        globalRegistry: globalRegistry,
+        operator: nodeOperator.publicKey,
+      })
+      .signers([nodeOperator])
+      .rpc();
+
+    console.log('Stake SOL tx:', tx);
+
+    const nodeData = await program.account.nodeAccount.fetch(nodeAccount);
+    expect(nodeData.isActive).toBe(true);
+  });
+
   it('Updates node heartbeat', async () => {
     if (!provider || !program) {
       console.warn('Skipping test: Solana not available');
@@ -74,8 +153,13 @@ describe('Node Registry Program', () => {
     }
     const bandwidthServedGb = new BN(100);
 
-    const [globalRegistry] = PublicKey.findProgramAddressSync(
-      [Buffer.from('registry')],
+    const registry = await program.account.globalRegistry.fetch(globalRegistry);
+    const [nodeEpochPoints] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('node_epoch'),
+        nodeAccount.toBuffer(),
+        registry.currentEpoch.toArrayLike(Buffer, 'le', 8),
+      ],
       program.programId
     );
 
@@ -84,6 +168,8 @@ describe('Node Registry Program', () => {
       .accountsPartial({
         nodeAccount: nodeAccount,
         globalRegistry: globalRegistry,
+        directoryPage: await nodeDirectoryPage(),
+        nodeEpochPoints,
         operator: nodeOperator.publicKey,
       })
       .signers([nodeOperator])
@@ -104,6 +190,7 @@ describe('Node Registry Program', () => {
       .deactivateNode()
       .accountsPartial({
         nodeAccount: nodeAccount,
+        directoryPage: await nodeDirectoryPage(),
         operator: nodeOperator.publicKey,
       })
       .signers([nodeOperator])
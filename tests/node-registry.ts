@@ -3,18 +3,37 @@ import { Program } from "@coral-xyz/anchor";
 import { NodeRegistry } from "../target/types/node_registry";
 import { expect } from "chai";
 
+const CAPABILITY_RELAY = 1 << 1;
+
 describe("node-registry", () => {
   const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
 
   const program = anchor.workspace.NodeRegistry as Program<NodeRegistry>;
   const authority = provider.wallet;
-  
+
   let globalRegistry: anchor.web3.PublicKey;
+  let directoryPage: anchor.web3.PublicKey;
   let nodeAccount: anchor.web3.PublicKey;
   let stakeVault: anchor.web3.PublicKey;
 
   const operator = anchor.web3.Keypair.generate();
+  const nodeIdentity = anchor.web3.Keypair.generate();
+
+  const metadata = (countryCode: string, ip: number[]) => ({
+    countryCode: Array.from(Buffer.from(countryCode)),
+    geohash: "9q8yy",
+    endpoints: [
+      {
+        addressKind: { ipv4: {} },
+        address: [...ip, ...new Array(12).fill(0)],
+        port: 443,
+        protocol: { tcp: {} },
+      },
+    ],
+    noisePublicKey: Array.from(anchor.web3.Keypair.generate().publicKey.toBytes()),
+    capabilities: CAPABILITY_RELAY,
+  });
 
   before(async () => {
     [globalRegistry] = anchor.web3.PublicKey.findProgramAddressSync(
@@ -22,8 +41,13 @@ describe("node-registry", () => {
       program.programId
     );
 
+    [directoryPage] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("directory"), new anchor.BN(0).toArrayLike(Buffer, "le", 4)],
+      program.programId
+    );
+
     [nodeAccount] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("node"), operator.publicKey.toBuffer()],
+      [Buffer.from("node"), nodeIdentity.publicKey.toBuffer()],
       program.programId
     );
 
@@ -43,33 +67,49 @@ describe("node-registry", () => {
   it("Initializes the registry", async () => {
     const tx = await program.methods
       .initialize()
-      .accounts({
+      .accountsPartial({
         globalRegistry,
         authority: authority.publicKey,
         protocolFeeVault: authority.publicKey,
       })
       .rpc();
 
+    await program.methods
+      .createDirectoryPage()
+      .accountsPartial({
+        directoryPage,
+        globalRegistry,
+        payer: authority.publicKey,
+      })
+      .rpc();
+
     const registryAccount = await program.account.globalRegistry.fetch(globalRegistry);
     expect(registryAccount.totalNodes).to.equal(0);
+    expect(registryAccount.directoryPages).to.equal(1);
     expect(registryAccount.authority.toString()).to.equal(authority.publicKey.toString());
   });
 
   it("Registers a new node", async () => {
     const tx = await program.methods
-      .registerNode("US-WEST-1", "192.168.1.1", 10)
-      .accounts({
+      .registerNode(metadata("US", [192, 168, 1, 1]), 10)
+      .accountsPartial({
         nodeAccount,
+        nodeIdentity: nodeIdentity.publicKey,
+        directoryPage,
         globalRegistry,
         operator: operator.publicKey,
+        stakeTokenVault: null,
+        stakeMint: null,
+        tokenProgram: null,
       })
-      .signers([operator])
+      .signers([operator, nodeIdentity])
       .rpc();
 
     const node = await program.account.nodeAccount.fetch(nodeAccount);
     expect(node.operator.toString()).to.equal(operator.publicKey.toString());
-    expect(node.location).to.equal("US-WEST-1");
-    expect(node.reputation).to.equal(100);
+    expect(node.nodeId.toString()).to.equal(nodeIdentity.publicKey.toString());
+    expect(Buffer.from(node.metadata.countryCode).toString()).to.equal("US");
+    expect(node.metadata.endpoints[0].port).to.equal(443);
     expect(node.isActive).to.equal(false);
 
     const registry = await program.account.globalRegistry.fetch(globalRegistry);
@@ -81,9 +121,10 @@ describe("node-registry", () => {
 
     const tx = await program.methods
       .stakeSol(new anchor.BN(stakeAmount))
-      .accounts({
+      .accountsPartial({
         nodeAccount,
         stakeVault,
+        directoryPage,
         globalRegistry,
         operator: operator.publicKey,
       })
@@ -96,11 +137,18 @@ describe("node-registry", () => {
   });
 
   it("Updates node heartbeat", async () => {
+    const [nodeEpochPoints] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("node_epoch"), nodeAccount.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
     const tx = await program.methods
       .updateHeartbeat(new anchor.BN(50))
-      .accounts({
+      .accountsPartial({
         nodeAccount,
         globalRegistry,
+        directoryPage,
+        nodeEpochPoints,
         operator: operator.publicKey,
       })
       .signers([operator])
@@ -110,23 +158,25 @@ describe("node-registry", () => {
     expect(node.totalBandwidthServed.toString()).to.equal("50");
   });
 
-  it("Updates reputation", async () => {
+  it("Adjusts reputation", async () => {
     const tx = await program.methods
-      .updateReputation(95)
-      .accounts({
+      .adjustReputation(-5, 1)
+      .accountsPartial({
         nodeAccount,
+        directoryPage,
         globalRegistry,
         authority: authority.publicKey,
       })
       .rpc();
 
     const node = await program.account.nodeAccount.fetch(nodeAccount);
-    expect(node.reputation).to.equal(95);
+    expect(node.reputationAdjustment).to.equal(-5);
   });
 
   it("Fails to stake with insufficient amount", async () => {
     const newOperator = anchor.web3.Keypair.generate();
-    
+    const newNodeIdentity = anchor.web3.Keypair.generate();
+
     await provider.connection.confirmTransaction(
       await provider.connection.requestAirdrop(
         newOperator.publicKey,
@@ -135,7 +185,7 @@ describe("node-registry", () => {
     );
 
     const [newNodeAccount] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("node"), newOperator.publicKey.toBuffer()],
+      [Buffer.from("node"), newNodeIdentity.publicKey.toBuffer()],
       program.programId
     );
 
@@ -145,27 +195,33 @@ describe("node-registry", () => {
     );
 
     await program.methods
-      .registerNode("EU-CENTRAL-1", "10.0.0.1", 5)
-      .accounts({
+      .registerNode(metadata("DE", [10, 0, 0, 1]), 5)
+      .accountsPartial({
         nodeAccount: newNodeAccount,
+        nodeIdentity: newNodeIdentity.publicKey,
+        directoryPage,
         globalRegistry,
         operator: newOperator.publicKey,
+        stakeTokenVault: null,
+        stakeMint: null,
+        tokenProgram: null,
       })
-      .signers([newOperator])
+      .signers([newOperator, newNodeIdentity])
       .rpc();
 
     try {
       await program.methods
         .stakeSol(new anchor.BN(50 * anchor.web3.LAMPORTS_PER_SOL))
-        .accounts({
+        .accountsPartial({
           nodeAccount: newNodeAccount,
           stakeVault: newStakeVault,
+          directoryPage,
           globalRegistry,
           operator: newOperator.publicKey,
         })
         .signers([newOperator])
         .rpc();
-      
+
       expect.fail("Should have failed with insufficient stake");
     } catch (error) {
       expect(error.toString()).to.include("InsufficientStake");